@@ -1,28 +1,56 @@
 use std::num::ParseIntError;
+use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 use regex::Regex;
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 pub enum CliError {
     #[error("Invalid int specification for size")]
     InvalidSizeInt(ParseIntError),
     #[error("Invalid size specification, please use the format: width[xheight](mm|cm|m)")]
     InvalidSize,
+    #[error("Invalid hole specification, please use the format: x,y,radius")]
+    InvalidHole,
+    #[error("Invalid tolerance, please use a number of mm greater than 0")]
+    InvalidTolerance,
 }
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 #[command(propagate_version = true)]
+#[command(args_conflicts_with_subcommands = true, arg_required_else_help = true)]
 /// A command line utility that take an .obj model and produce a .svg representations of lines to etch
 /// to create a holographic image of that model
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub args: Option<Args>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    Regenerate(Regenerate),
+}
+
+/// The options for scribing a model
+#[derive(Parser)]
 pub struct Args {
     /// Input file. Expects a .obj file
     #[arg(short, long)]
     pub input: String,
 
-    /// Output file. Expects a .svg file
+    /// Output file, in the format given by its extension: .svg, .json for the scribe plan,
+    /// .gcode/.nc/.ngc for CNC scribing, .hpgl/.plt for plotters and cutters, .dxf for CAD
+    /// and laser software, or .pdf for printed templates.
+    /// SVG output is scribed straight into the file without holding every line in memory,
+    /// unless --merge-circles, --plate, --hole, --optimize-toolpath, --tile, --depth-levels,
+    /// --group-by, --fiducials, --scale-bar, --compass-kit or the [plate], [fiducials] or
+    /// grouping settings of the config file are used, which need all the lines at once
     #[arg(short, long)]
     pub output: String,
 
@@ -33,6 +61,318 @@ pub struct Args {
     /// The density of lines etched per mm. Defaults to 1.
     #[arg(long, default_value_t = 1)]
     pub stroke_density: usize,
+
+    /// Config file (.toml) with styles, per-layer styles, background and margins, and the
+    /// settings for DXF, G-code, HPGL and PDF output in [dxf], [gcode], [hpgl] and [pdf] tables.
+    /// The plate outline and tiling go in [plate] and [tiling] tables, fiducials and back-side
+    /// scribing in [fiducials] and [back_side] tables.
+    /// The options below take precedence over the values in this file. The contents of the
+    /// file are recorded in the output, for `holocli regenerate`.
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// Also write a kit for scribing by hand with a compass next to the output: a template
+    /// with numbered circle centers (<output>.compass.svg) and their radii as a table
+    /// (<output>.compass.csv and <output>.compass.md)
+    #[arg(long)]
+    pub compass_kit: bool,
+
+    /// Radii closer than this many mm share a compass setting in the compass kit
+    #[arg(long, default_value_t = 0.1)]
+    pub compass_step: f32,
+
+    /// Stroke color of scribed lines
+    #[arg(long)]
+    pub stroke: Option<String>,
+
+    /// Width of scribed lines in mm
+    #[arg(long)]
+    pub stroke_width: Option<f32>,
+
+    /// Fill color of scribed shapes
+    #[arg(long)]
+    pub fill: Option<String>,
+
+    /// Background color of the canvas. Transparent if not set
+    #[arg(long)]
+    pub background: Option<String>,
+
+    /// Margin around the scribed lines, as a fraction of their width and height
+    #[arg(long)]
+    pub margin: Option<f32>,
+
+    /// Margin in mm between the edge of the canvas and the model
+    #[arg(long)]
+    pub margin_mm: Option<f32>,
+
+    /// How the model is scaled onto the canvas
+    #[arg(long, value_enum)]
+    pub fit: Option<FitMode>,
+
+    /// Millimeters per model unit, for the fixed fit
+    #[arg(long, required_if_eq("fit", "fixed"))]
+    pub mm_per_unit: Option<f32>,
+
+    /// Scale the scribed lines to touch the edges of the canvas: a contain fit without margins.
+    /// Takes precedence over the margin and fit options
+    #[arg(long)]
+    pub fit_plate: bool,
+
+    /// Outline of the plate to clip the scribed lines to: rectangle or circle for the largest
+    /// one fitting the canvas, or an .svg or .dxf file with the outline as a polygon in mm, seen
+    /// from the front with --back-side.
+    /// Curves of SVG paths are flattened to within the tolerance of the [plate] table
+    #[arg(long)]
+    pub plate: Option<String>,
+
+    /// Radius in mm of the corners of a rectangular plate
+    #[arg(long, default_value_t = 0.0)]
+    pub corner_radius: f32,
+
+    /// A hole in the plate not to scribe over, as x,y,radius in mm from the top left corner
+    /// of the canvas, seen from the front with --back-side. Can be repeated
+    #[arg(long, value_parser = parse_hole)]
+    pub hole: Vec<Hole>,
+
+    /// Intensity, from 0 to 1, of the lines of the point nearest to the plate. Machine output
+    /// can turn it into plunge depth or tool power, see the [gcode] table of the config file
+    #[arg(long)]
+    pub intensity_near: Option<f32>,
+
+    /// Intensity, from 0 to 1, of the lines of the point farthest from the plate
+    #[arg(long)]
+    pub intensity_far: Option<f32>,
+
+    /// Snap the depth of the points to this many levels, so that the circles come in as many
+    /// radii, and scribe the levels one after the other. Each level is written in a group of
+    /// its own, named level-<n> in the order the levels are reported, unless --group-by or
+    /// the config file groups otherwise
+    #[arg(long)]
+    pub depth_levels: Option<usize>,
+
+    /// How the depth levels are chosen
+    #[arg(long, value_enum, default_value_t = LevelChoice::KMeans)]
+    pub level_method: LevelChoice,
+
+    /// Write the scribed lines in named groups: Inkscape layers in SVG, DXF layers, and
+    /// sections of G-code
+    #[arg(long, value_enum)]
+    pub group_by: Option<GroupBy>,
+
+    /// Depth in mm of the bands grouped by with --group-by depth-band
+    #[arg(long, required_if_eq("group_by", "depth-band"))]
+    pub group_band: Option<f32>,
+
+    /// Leave out circles and arcs scribed into the groove of an earlier one: those with
+    /// centers, radii and ends closer than --merge-tolerance
+    #[arg(long)]
+    pub merge_circles: bool,
+
+    /// Distance in mm under which circles are merged. Defaults to the stroke width
+    #[arg(long)]
+    pub merge_tolerance: Option<f32>,
+
+    /// Replace circles, arcs and curves with polylines deviating at most this many mm from
+    /// them, for controllers, plotters and software that can't handle curves
+    #[arg(long, value_parser = parse_tolerance)]
+    pub flatten: Option<f32>,
+
+    /// Which elements shapes are written as in SVG output
+    #[arg(long, value_enum, default_value_t = Elements::Native)]
+    pub svg_elements: Elements,
+
+    /// Split the output over plates of this size, written as one file per plate named after
+    /// its row and column, like <output>.r1c2.svg, with an overview in <output>.tiles.svg
+    #[arg(long, value_parser = parse_size)]
+    pub tile: Option<CanvasSize>,
+
+    /// How far neighbouring plates overlap in mm
+    #[arg(long)]
+    pub tile_overlap: Option<f32>,
+
+    /// Scribe corner crosses, crop marks, a center mark and a scale bar outside the scribed
+    /// lines, on every plate when tiling, for lining the plate up. Their positions are
+    /// written into the output. Set which marks in the [fiducials] table of the config file
+    #[arg(long)]
+    pub fiducials: bool,
+
+    /// Length of the scale bar in mm. Implies --fiducials
+    #[arg(long)]
+    pub scale_bar: Option<f32>,
+
+    /// Scribe on the back of a transparent plate, so the front stays glossy: the output is
+    /// mirrored and depths are corrected for looking at the scratches through the plate. Set
+    /// the plate in the [back_side] table of the config file
+    #[arg(long)]
+    pub back_side: bool,
+
+    /// The center line the output is mirrored about for back-side scribing. Implies
+    /// --back-side
+    #[arg(long, value_enum)]
+    pub mirror_axis: Option<Mirror>,
+
+    /// Thickness in mm of the plate scribed on the back. Implies --back-side
+    #[arg(long)]
+    pub plate_thickness: Option<f32>,
+
+    /// Refractive index of the plate scribed on the back, 1.49 for acrylic. Implies
+    /// --back-side
+    #[arg(long)]
+    pub refractive_index: Option<f32>,
+
+    /// Reorder the scribed lines to shorten the travel between them, choosing where and in
+    /// which direction each is scribed. Lines are only reordered within their group, layer
+    /// and, with --depth-levels, depth level
+    #[arg(long)]
+    pub optimize_toolpath: bool,
+
+    /// Horizontal alignment of the model on the canvas
+    #[arg(long, value_enum)]
+    pub align_x: Option<Alignment>,
+
+    /// Vertical alignment of the model on the canvas
+    #[arg(long, value_enum)]
+    pub align_y: Option<Alignment>,
+
+    /// How each point is scribed
+    #[arg(long, value_enum, default_value_t = Strategy::Circle)]
+    pub strategy: Strategy,
+
+    /// Horizontal viewing cone in degrees, for the arc, ellipse and parabola strategies
+    #[arg(long, default_value_t = 30.0)]
+    pub horizontal_cone: f32,
+
+    /// Vertical viewing cone in degrees, for the arc, ellipse and parabola strategies
+    #[arg(long, default_value_t = 20.0)]
+    pub vertical_cone: f32,
+
+    /// The z value of the plate surface in model units. Points in front of it become real images
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub reference_plane: f32,
+
+    /// How the depth of a point is turned into the radius of its circle
+    #[arg(long, value_enum, default_value_t = DepthCurve::Linear)]
+    pub depth_mapping: DepthCurve,
+
+    /// Radius in mm per mm of depth for the linear and gamma mappings
+    #[arg(long, default_value_t = 0.25)]
+    pub depth_scale: f32,
+
+    /// Exponent of the gamma mapping
+    #[arg(long, default_value_t = 1.0)]
+    pub gamma: f32,
+
+    /// Calibration table for the lookup mapping. Expects a .csv file with depth,radius rows in mm
+    #[arg(long, required_if_eq("depth_mapping", "lookup"))]
+    pub depth_table: Option<String>,
+
+    /// Distance from the plate to the viewer in mm, for the physical mapping
+    #[arg(long, default_value_t = 500.0)]
+    pub viewing_distance: f32,
+
+    /// Angle of the line of sight from the plate normal in degrees, for the physical mapping
+    /// and the arc, ellipse and parabola strategies
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub viewer_angle: f32,
+
+    /// Angle of the light source from the plate normal in degrees, for the physical mapping
+    /// and the arc, ellipse and parabola strategies
+    #[arg(long, default_value_t = 45.0, allow_negative_numbers = true)]
+    pub light_angle: f32,
+}
+
+/// Fit modes of the model on the canvas
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum FitMode {
+    /// As large as possible with the whole model on the canvas
+    Contain,
+    /// As small as possible with the whole canvas covered by the model
+    Cover,
+    /// A fixed number of mm per model unit, see --mm-per-unit
+    Fixed,
+}
+
+/// Alignment of the model along an axis of the canvas
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Alignment {
+    /// Left or top
+    Start,
+    Center,
+    /// Right or bottom
+    End,
+}
+
+/// Makes an output of holocli again, from the model and options recorded in it
+#[derive(clap::Args)]
+pub struct Regenerate {
+    /// A file written by holocli, in any output format. HPGL files only have the options
+    /// recorded with `comments = true` in the [hpgl] table
+    pub file: PathBuf,
+
+    /// Where to write the output, in the format given by its extension. Defaults to where it
+    /// was written the first time
+    #[arg(short, long)]
+    pub output: Option<String>,
+}
+
+/// SVG elements shapes are written as
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Elements {
+    /// <circle> for circles, <path> for everything else
+    Native,
+    /// <path> for everything
+    Paths,
+    /// <path> with relative commands for everything
+    RelativeArcs,
+}
+
+/// Lines the output is mirrored about for back-side scribing
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Mirror {
+    /// Swap left and right
+    Vertical,
+    /// Swap top and bottom
+    Horizontal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum GroupBy {
+    /// The objects and groups of the model
+    Source,
+    /// The layers the strategy draws into
+    Layer,
+    /// Bands of depth, --group-band mm deep
+    DepthBand,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum LevelChoice {
+    /// Equally deep bands between the nearest and the farthest point
+    Uniform,
+    /// Clusters following the depth distribution
+    #[value(name = "kmeans")]
+    KMeans,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Strategy {
+    /// Full circles
+    Circle,
+    /// Only the arcs of the circles seen from inside the viewing cone
+    Arc,
+    /// Elliptical arcs, foreshortened for a viewer looking at the plate from an angle
+    Ellipse,
+    /// Parabolic scratches, for overhead and oblique lighting
+    Parabola,
+}
+
+/// Depth-to-radius mapping curves
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum DepthCurve {
+    Linear,
+    Gamma,
+    Lookup,
+    Physical,
 }
 
 /// Represents a size in millimeters
@@ -42,6 +382,32 @@ pub struct CanvasSize {
     pub height: usize,
 }
 
+/// A circular hole in the plate, in mm
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hole {
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32,
+}
+
+fn parse_hole(arg: &str) -> Result<Hole, CliError> {
+    let values: Vec<f32> = arg
+        .split(',')
+        .map(|value| value.trim().parse().map_err(|_| CliError::InvalidHole))
+        .collect::<Result<_, _>>()?;
+    match values.as_slice() {
+        &[x, y, radius] if radius > 0.0 => Ok(Hole { x, y, radius }),
+        _ => Err(CliError::InvalidHole),
+    }
+}
+
+fn parse_tolerance(arg: &str) -> Result<f32, CliError> {
+    match arg.trim().parse() {
+        Ok(tolerance) if tolerance > 0.0 => Ok(tolerance),
+        _ => Err(CliError::InvalidTolerance),
+    }
+}
+
 fn parse_size(arg: &str) -> Result<CanvasSize, CliError> {
     let re = Regex::new(r"^(\d+|\d+x\d+)(mm|cm|m)$").unwrap();
     if let Some(cap) = re.captures(arg) {
@@ -56,17 +422,13 @@ fn parse_size(arg: &str) -> Result<CanvasSize, CliError> {
         let size_str = cap.get(1).expect("Regex requires a size").as_str();
         let (width, height) = if let Some((width, height)) = size_str.split_once('x') {
             (
-                width
-                    .parse::<usize>()
-                    .map_err(|e| CliError::InvalidSizeInt(e))?,
-                height
-                    .parse::<usize>()
-                    .map_err(|e| CliError::InvalidSizeInt(e))?,
+                width.parse::<usize>().map_err(CliError::InvalidSizeInt)?,
+                height.parse::<usize>().map_err(CliError::InvalidSizeInt)?,
             )
         } else {
             let width = size_str
                 .parse::<usize>()
-                .map_err(|e| CliError::InvalidSizeInt(e))?;
+                .map_err(CliError::InvalidSizeInt)?;
             (width, width)
         };
 
@@ -84,7 +446,7 @@ fn parse_size(arg: &str) -> Result<CanvasSize, CliError> {
 mod tests {
     use std::num::IntErrorKind;
 
-    use crate::cli::{parse_size, CanvasSize, CliError};
+    use crate::cli::{parse_hole, parse_size, parse_tolerance, CanvasSize, CliError, Hole};
 
     #[test]
     fn test_parse_size() {
@@ -133,4 +495,27 @@ mod tests {
             panic!("Unexpected error type")
         }
     }
+
+    #[test]
+    fn test_parse_hole() {
+        assert_eq!(
+            parse_hole("10,20.5, 3"),
+            Ok(Hole {
+                x: 10.0,
+                y: 20.5,
+                radius: 3.0
+            })
+        );
+        assert_eq!(parse_hole("10,20"), Err(CliError::InvalidHole));
+        assert_eq!(parse_hole("10,20,-1"), Err(CliError::InvalidHole));
+        assert_eq!(parse_hole("a,b,c"), Err(CliError::InvalidHole));
+    }
+
+    #[test]
+    fn test_parse_tolerance() {
+        assert_eq!(parse_tolerance("0.05"), Ok(0.05));
+        assert_eq!(parse_tolerance("0"), Err(CliError::InvalidTolerance));
+        assert_eq!(parse_tolerance("-0.1"), Err(CliError::InvalidTolerance));
+        assert_eq!(parse_tolerance("NaN"), Err(CliError::InvalidTolerance));
+    }
 }
@@ -0,0 +1,295 @@
+use std::error::Error;
+
+use glam::Vec2;
+use serde::Deserialize;
+
+use crate::cli::{Alignment, Args, DepthCurve, Elements, FitMode, GroupBy, Mirror};
+use holoscribe::{
+    export::{DxfConfig, GcodeConfig, HpglConfig, PdfConfig, SvgElements},
+    scriber::{
+        Align, BackSide, DepthMapping, Fiducials, Fit, Grouping, MirrorAxis, Outline, PlateClip,
+        ScribePlan, ScriberConfig, Tiling, ViewingCone, ViewingGeometry,
+    },
+};
+
+/// Contents of the config file: the scriber config at the top level, and a table per
+/// output format that needs more settings
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ConfigFile {
+    #[serde(flatten)]
+    pub scriber: ScriberConfig,
+    pub dxf: DxfConfig,
+    pub gcode: GcodeConfig,
+    pub hpgl: HpglConfig,
+    pub pdf: PdfConfig,
+    pub plate: PlateClip,
+    pub tiling: Tiling,
+    pub fiducials: Option<Fiducials>,
+    pub back_side: Option<BackSide>,
+}
+
+/// Parse the contents of the config file, if any
+pub fn config_file(config: Option<&str>) -> Result<ConfigFile, Box<dyn Error>> {
+    let file: ConfigFile = match config {
+        Some(config) => toml::from_str(config)?,
+        None => ConfigFile::default(),
+    };
+    for (table, tolerance) in [
+        ("dxf", file.dxf.tolerance),
+        ("gcode", file.gcode.tolerance),
+        ("hpgl", file.hpgl.tolerance),
+        ("plate", file.plate.tolerance),
+    ] {
+        // Flattening curves to within no distance at all would take infinitely many segments
+        if tolerance.is_nan() || tolerance <= 0.0 {
+            return Err(
+                format!("[{table}] tolerance must be greater than 0, not {tolerance}").into(),
+            );
+        }
+    }
+    file.pdf.validate()?;
+    Ok(file)
+}
+
+/// Apply the style options to the scriber config from the config file
+pub fn scriber_config(args: &Args, mut config: ScriberConfig) -> ScriberConfig {
+    if let Some(stroke) = &args.stroke {
+        config.style.stroke = stroke.clone();
+    }
+    if let Some(stroke_width) = args.stroke_width {
+        config.style.stroke_width = Some(stroke_width);
+    }
+    if let Some(fill) = &args.fill {
+        config.style.fill = fill.clone();
+    }
+    if let Some(background) = &args.background {
+        config.background = Some(background.clone());
+    }
+    if let Some(margin) = args.margin {
+        config.margin_percentage = margin;
+    }
+    if let Some(margin) = args.margin_mm {
+        config.margin = margin;
+    }
+    if let Some(fit) = args.fit {
+        config.fit = match fit {
+            FitMode::Contain => Fit::Contain,
+            FitMode::Cover => Fit::Cover,
+            FitMode::Fixed => Fit::Fixed {
+                mm_per_unit: args.mm_per_unit.expect("clap requires a scale"),
+            },
+        };
+    }
+    if args.fit_plate {
+        config.margin_percentage = 0.0;
+        config.margin = 0.0;
+        config.fit = Fit::Contain;
+    }
+    if let Some(align) = args.align_x {
+        config.align.0 = alignment(align);
+    }
+    if let Some(align) = args.align_y {
+        config.align.1 = alignment(align);
+    }
+    if let Some(near) = args.intensity_near {
+        config.intensity.near = near;
+    }
+    if let Some(far) = args.intensity_far {
+        config.intensity.far = far;
+    }
+    match args.group_by {
+        Some(GroupBy::Source) => config.grouping = None,
+        Some(GroupBy::Layer) => config.grouping = Some(Grouping::Layer),
+        Some(GroupBy::DepthBand) => {
+            config.grouping = Some(Grouping::DepthBand {
+                band: args.group_band.expect("clap requires a band"),
+            })
+        }
+        // Each depth level is scribed in a pass of its own, unless grouped otherwise
+        None if args.depth_levels.is_some() && config.grouping.is_none() => {
+            config.grouping = Some(Grouping::DepthLevel)
+        }
+        None => {}
+    }
+    config
+}
+
+/// The tiling settings from the config file with the plate size and overlap options, if
+/// the output is split over plates. Fails early for an overlap the plates can't have
+pub fn tiling(args: &Args, mut tiling: Tiling) -> Result<Option<Tiling>, Box<dyn Error>> {
+    let Some(size) = &args.tile else {
+        return Ok(None);
+    };
+    tiling.plate_size = (size.width as f32, size.height as f32);
+    if let Some(overlap) = args.tile_overlap {
+        tiling.overlap = overlap;
+    }
+    tiling.validate()?;
+    Ok(Some(tiling))
+}
+
+/// The back side settings from the config file, or the default ones if back-side scribing
+/// is enabled by the options, with the options applied
+pub fn back_side(args: &Args, back_side: Option<BackSide>) -> Option<BackSide> {
+    let enabled = args.back_side
+        || args.mirror_axis.is_some()
+        || args.plate_thickness.is_some()
+        || args.refractive_index.is_some();
+    let mut back_side = back_side.or(enabled.then(BackSide::default))?;
+    if let Some(axis) = args.mirror_axis {
+        back_side.axis = match axis {
+            Mirror::Vertical => MirrorAxis::Vertical,
+            Mirror::Horizontal => MirrorAxis::Horizontal,
+        };
+    }
+    if let Some(thickness) = args.plate_thickness {
+        back_side.thickness = thickness;
+    }
+    if let Some(index) = args.refractive_index {
+        back_side.refractive_index = index;
+    }
+    Some(back_side)
+}
+
+/// The fiducials from the config file, or the default ones if they are enabled by the options
+pub fn fiducials(args: &Args, fiducials: Option<Fiducials>) -> Option<Fiducials> {
+    let mut fiducials =
+        fiducials.or((args.fiducials || args.scale_bar.is_some()).then(Fiducials::default));
+    if let (Some(fiducials), Some(length)) = (&mut fiducials, args.scale_bar) {
+        fiducials.scale_bar = Some(length);
+    }
+    fiducials
+}
+
+/// Apply the plate options to the clipping settings from the config file
+pub fn plate_clip(
+    args: &Args,
+    mut clip: PlateClip,
+    plan: &ScribePlan,
+) -> Result<PlateClip, Box<dyn Error>> {
+    if let Some(plate) = &args.plate {
+        let (width, height) = plan.canvas_size;
+        clip.outline = Some(match plate.as_str() {
+            "rectangle" => Outline::Rectangle {
+                x: 0.0,
+                y: 0.0,
+                width,
+                height,
+                corner_radius: args.corner_radius,
+            },
+            "circle" => Outline::inscribed_circle(plan),
+            path if path.ends_with(".dxf") => Outline::polygon_from_dxf(path, height)?,
+            path => Outline::polygon_from_svg(path, clip.tolerance)?,
+        });
+    }
+    clip.masks
+        .extend(args.hole.iter().map(|hole| Outline::Circle {
+            center: Vec2::new(hole.x, hole.y),
+            radius: hole.radius,
+        }));
+    Ok(clip)
+}
+
+/// The generation parameters, one per line, for formats that can describe themselves
+pub fn parameters(args: &Args, mapping: &DepthMapping) -> Vec<String> {
+    vec![
+        format!("Model: {}", args.input),
+        format!(
+            "Strategy: {:?}, {} points per mm, reference plane at z = {}",
+            args.strategy, args.stroke_density, args.reference_plane
+        ),
+        format!("Depth mapping: {mapping:?}"),
+        format!(
+            "Viewer at {} mm and {} deg, light at {} deg, viewing cone {} x {} deg",
+            args.viewing_distance,
+            args.viewer_angle,
+            args.light_angle,
+            args.horizontal_cone,
+            args.vertical_cone
+        ),
+    ]
+}
+
+/// Build the depth-to-radius mapping selected by the depth options
+pub fn depth_mapping(args: &Args) -> Result<DepthMapping, Box<dyn Error>> {
+    Ok(match args.depth_mapping {
+        DepthCurve::Linear => DepthMapping::Linear {
+            scale: args.depth_scale,
+        },
+        DepthCurve::Gamma => DepthMapping::Gamma {
+            scale: args.depth_scale,
+            gamma: args.gamma,
+        },
+        DepthCurve::Lookup => {
+            let path = args
+                .depth_table
+                .clone()
+                .expect("clap requires a depth table");
+            DepthMapping::lookup_from_csv(path)?
+        }
+        DepthCurve::Physical => DepthMapping::Physical(viewing_geometry(args)),
+    })
+}
+
+/// Build the viewing cone from the cone options
+pub fn viewing_cone(args: &Args) -> ViewingCone {
+    ViewingCone {
+        horizontal: args.horizontal_cone,
+        vertical: args.vertical_cone,
+    }
+}
+
+/// Build the viewer and light geometry from the viewing options
+pub fn viewing_geometry(args: &Args) -> ViewingGeometry {
+    ViewingGeometry {
+        viewer_distance: args.viewing_distance,
+        viewer_angle: args.viewer_angle,
+        light_angle: args.light_angle,
+    }
+}
+
+pub fn svg_elements(elements: Elements) -> SvgElements {
+    match elements {
+        Elements::Native => SvgElements::Native,
+        Elements::Paths => SvgElements::Paths,
+        Elements::RelativeArcs => SvgElements::RelativeArcs,
+    }
+}
+
+fn alignment(align: Alignment) -> Align {
+    match align {
+        Alignment::Start => Align::Start,
+        Alignment::Center => Align::Center,
+        Alignment::End => Align::End,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[test]
+    fn test_config_file() {
+        assert!(config_file(Some("[gcode]\ntolerance = 0.05")).is_ok());
+        assert!(config_file(Some("[gcode]\ntolerance = 0")).is_err());
+        assert!(config_file(Some("[plate]\ntolerance = -1")).is_err());
+        assert!(config_file(Some("[pdf]\noverlap = 200")).is_err());
+    }
+
+    #[test]
+    fn test_tile_overlap() {
+        let args = |overlap: &str| {
+            let command_line = ["holocli", "-i", "m.obj", "-o", "m.svg", "-c", "100mm"];
+            let overlap = format!("--tile-overlap={overlap}");
+            let tile = ["--tile", "50x40mm", &overlap];
+            Args::try_parse_from(command_line.into_iter().chain(tile)).unwrap()
+        };
+        let tiling = |overlap| tiling(&args(overlap), Tiling::default());
+        assert_eq!(tiling("10").unwrap().unwrap().overlap, 10.0);
+        assert!(tiling("40").is_err());
+        assert!(tiling("-1").is_err());
+    }
+}
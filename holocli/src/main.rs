@@ -2,29 +2,27 @@
 //cli accepts obj file and svg location (and optional parameters)
 
 mod cli;
+mod config;
+mod output;
+mod pipeline;
+mod provenance;
 
 use std::error::Error;
+use std::fs;
 
 use clap::Parser;
-use cli::Args;
-use holoscribe::{model::ObjInterpolator, scriber};
+use cli::{Cli, Command};
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
-    let user_defined_model = ObjInterpolator::from_file(args.input).unwrap();
-    let interpolated_points = user_defined_model.interpolate_edges(args.stroke_density);
-
-    let circle_strat = scriber::CircleScriber::new();
-    let scriber = scriber::Scriber::new(
-        circle_strat,
-        (args.canvas_size.width, args.canvas_size.height),
-    );
-
-    let svg = scriber.scribe(&interpolated_points);
-
-    svg::save(args.output, &svg).expect("failed to save");
-    Ok(())
+    let cli = Cli::parse();
+    let args = match (cli.command, cli.args) {
+        (Some(Command::Regenerate(regenerate_args)), _) => {
+            return provenance::regenerate(regenerate_args)
+        }
+        (None, Some(args)) => args,
+        (None, None) => unreachable!("clap requires options or a subcommand"),
+    };
+    let config = args.config.as_ref().map(fs::read_to_string).transpose()?;
+    let arguments = provenance::without_output(std::env::args().skip(1));
+    pipeline::run(args, arguments, config)
 }
-
-#[cfg(test)]
-mod tests {}
@@ -0,0 +1,118 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::config::ConfigFile;
+use holoscribe::{
+    export::{
+        CompassKit, DxfExporter, Exporter, GcodeExporter, HpglExporter, PdfExporter, SvgExporter,
+        TileIndex,
+    },
+    scriber::{Fiducials, ScribePlan, Tiling},
+};
+
+/// Write a file per plate next to the output file, and an overview of where they go
+pub fn tile(
+    plan: &ScribePlan,
+    tiling: &Tiling,
+    fiducials: Option<&Fiducials>,
+    svg: &SvgExporter,
+    file: &ConfigFile,
+    parameters: &[String],
+    output: &str,
+) -> Result<(), Box<dyn Error>> {
+    let output = Path::new(output);
+    let extension = output
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("svg");
+    let mut tiles = tiling.tiles(plan)?;
+    for tile in &mut tiles {
+        if let Some(fiducials) = fiducials {
+            add_fiducials(fiducials, &mut tile.plan);
+        }
+        let path = output.with_extension(format!("{}.{extension}", tile.name()));
+        let mut parameters = parameters.to_vec();
+        parameters.push(format!(
+            "Plate {} at {} x {} mm",
+            tile.name(),
+            tile.origin.x,
+            tile.origin.y
+        ));
+        export(&tile.plan, svg, file, &parameters, &path.to_string_lossy())?;
+        println!("plate {}: {}", tile.name(), tile.clip);
+    }
+
+    let index = TileIndex::new(plan, &tiles);
+    let mut writer = BufWriter::new(File::create(output.with_extension("tiles.svg"))?);
+    index.write_svg(&mut writer)?;
+    writer.flush()?;
+    let (columns, rows) = tiling.grid(plan.canvas_size);
+    println!("split over {columns} x {rows} plates");
+    Ok(())
+}
+
+/// Add the fiducials to the plan, reporting any that did not fit
+pub fn add_fiducials(fiducials: &Fiducials, plan: &mut ScribePlan) {
+    let skipped = fiducials.apply(plan);
+    print!("fiducials: {} marks", plan.fiducials.len());
+    if skipped > 0 {
+        print!(", left out {skipped} overlapping the scribed lines or off the canvas");
+    }
+    println!();
+}
+
+/// Write the compass kit of the plan next to the output file
+pub fn compass_kit(plan: &ScribePlan, step: f32, output: &str) -> Result<(), Box<dyn Error>> {
+    let kit = CompassKit::new(plan, step);
+    let path = |extension: &str| Path::new(output).with_extension(extension);
+
+    let mut writer = BufWriter::new(File::create(path("compass.svg"))?);
+    kit.write_svg(&mut writer)?;
+    writer.flush()?;
+    let mut writer = BufWriter::new(File::create(path("compass.csv"))?);
+    kit.write_csv(&mut writer)?;
+    writer.flush()?;
+    let mut writer = BufWriter::new(File::create(path("compass.md"))?);
+    kit.write_markdown(&mut writer)?;
+    writer.flush()?;
+
+    println!(
+        "compass kit: {} centers, {} compass settings",
+        kit.entries.len(),
+        kit.settings()
+    );
+    if kit.skipped > 0 {
+        println!(
+            "compass kit: left out {} shapes that aren't circles or arcs",
+            kit.skipped
+        );
+    }
+    Ok(())
+}
+
+/// Write the plan in the format matching the extension of the output file
+pub fn export(
+    plan: &ScribePlan,
+    svg: &SvgExporter,
+    file: &ConfigFile,
+    parameters: &[String],
+    output: &str,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = BufWriter::new(File::create(output)?);
+    match Path::new(output).extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::to_writer_pretty(&mut writer, plan)?,
+        Some("gcode" | "nc" | "ngc") => {
+            GcodeExporter::new(file.gcode.clone()).export(plan, &mut writer)?
+        }
+        Some("dxf") => DxfExporter::new(file.dxf.clone()).export(plan, &mut writer)?,
+        Some("pdf") => PdfExporter::new(file.pdf.clone())
+            .parameters(parameters.to_vec())
+            .export(plan, &mut writer)?,
+        Some("hpgl" | "plt") => HpglExporter::new(file.hpgl.clone()).export(plan, &mut writer)?,
+        _ => svg.export(plan, &mut writer)?,
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,333 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use glam::Vec3;
+
+use crate::cli::{Args, GroupBy, LevelChoice, Strategy};
+use crate::config::{
+    back_side, config_file, depth_mapping, fiducials, parameters, plate_clip, scriber_config,
+    svg_elements, tiling, viewing_cone, viewing_geometry, ConfigFile,
+};
+use crate::output::{add_fiducials, compass_kit, export, tile};
+use crate::provenance::provenance;
+use holoscribe::{
+    export::{Provenance, SvgExporter},
+    model::ObjInterpolator,
+    scriber::{
+        self, BackSide, CircleMerger, DepthLevel, DepthQuantizer, LevelMethod, QuantizeReport,
+        ScribeLengths, ScribePlan, Scriber, ScriberConfig, ToolpathOptimizer,
+    },
+};
+
+/// Scribe the model as the options say. `arguments` are the options as they were given,
+/// without the output, and `config` the contents of the config file, both recorded in the
+/// provenance of the output
+pub fn run(
+    args: Args,
+    arguments: Vec<String>,
+    config: Option<String>,
+) -> Result<(), Box<dyn Error>> {
+    let file = config_file(config.as_deref())?;
+    let provenance = provenance(&args, arguments, config)?;
+    let config = scriber_config(&args, file.scriber.clone());
+    let tiling = tiling(&args, file.tiling.clone())?;
+    let mapping = depth_mapping(&args)?;
+    let parameters = parameters(&args, &mapping);
+    let user_defined_model = ObjInterpolator::from_file(args.input.clone())
+        .map_err(|error| format!("can't load {}: {error}", args.input))?;
+    let by_source = args.group_by == Some(GroupBy::Source);
+    let mut groups = if by_source {
+        user_defined_model.interpolate_groups(args.stroke_density)
+    } else {
+        let points = user_defined_model.interpolate_edges(args.stroke_density);
+        vec![(String::new(), points)]
+    };
+    let levels = args
+        .depth_levels
+        .map(|levels| quantize(&mut groups, levels, args.level_method));
+
+    let back_side = back_side(&args, file.back_side.clone());
+    let mut circle_strat =
+        scriber::CircleScriber::with_mapping(mapping).reference_plane(args.reference_plane);
+    if let Some(back_side) = &back_side {
+        circle_strat = circle_strat.back_side(back_side.clone());
+    }
+    let canvas_size = (args.canvas_size.width, args.canvas_size.height);
+    let scriber = match args.strategy {
+        Strategy::Circle => scriber::Scriber::with_config(circle_strat, canvas_size, config),
+        Strategy::Arc => {
+            let arc_strat = scriber::ArcScriber::new(
+                circle_strat,
+                viewing_geometry(&args),
+                viewing_cone(&args),
+            );
+            scriber::Scriber::with_config(arc_strat, canvas_size, config)
+        }
+        Strategy::Ellipse => {
+            let ellipse_strat = scriber::EllipseScriber::new(
+                circle_strat,
+                viewing_geometry(&args),
+                viewing_cone(&args),
+            );
+            scriber::Scriber::with_config(ellipse_strat, canvas_size, config)
+        }
+        Strategy::Parabola => {
+            let parabola_strat = scriber::ParabolaScriber::new(
+                circle_strat,
+                viewing_geometry(&args),
+                viewing_cone(&args),
+            );
+            scriber::Scriber::with_config(parabola_strat, canvas_size, config)
+        }
+    };
+
+    let svg = SvgExporter::new(scriber.config().clone()).elements(svg_elements(args.svg_elements));
+    let fiducials = fiducials(&args, file.fiducials.clone());
+    if streams(&args, &file, scriber.config(), fiducials.is_some()) {
+        let points = &groups[0].1;
+        return stream(
+            &args,
+            &scriber,
+            points,
+            &svg,
+            provenance,
+            back_side.as_ref(),
+        );
+    }
+
+    let mut plan = if by_source {
+        scriber.plan_groups(&groups)
+    } else {
+        scriber.plan(&groups[0].1)
+    };
+    plan.provenance = Some(provenance);
+    if args.strategy == Strategy::Arc {
+        println!("{}", ScribeLengths::of(&plan));
+    }
+    if let Some(levels) = &levels {
+        println!("{}", QuantizeReport::new(levels, plan.mm_per_unit()));
+    }
+    if args.merge_circles {
+        let tolerance = args
+            .merge_tolerance
+            .unwrap_or(scriber.config().style.stroke_width_mm(plan.mm_per_unit()));
+        println!("{}", CircleMerger::new(tolerance).merge(&mut plan));
+    }
+    let clip = plate_clip(&args, file.plate.clone(), &plan)?;
+    if !clip.is_empty() {
+        println!("{}", clip.clip(&mut plan));
+    }
+    // The plate outline and holes are given as seen from the front, so the plan is clipped
+    // before it is turned over
+    if let Some(back_side) = &back_side {
+        back_side.mirror(&mut plan);
+    }
+    if levels.is_some() {
+        plan.sort_by_depth();
+    }
+    if args.optimize_toolpath {
+        // Reordering within each depth level keeps them scribed one after the other
+        let optimizer = ToolpathOptimizer::new().keep_depth_order(levels.is_some());
+        println!("{}", optimizer.optimize(&mut plan));
+    }
+    // The kit is made of the circles and arcs, before flattening turns them into polylines
+    // and without the fiducials
+    if args.compass_kit {
+        compass_kit(&plan, args.compass_step, &args.output)?;
+    }
+    if let Some(tolerance) = args.flatten {
+        plan.flatten(tolerance);
+    }
+    match &tiling {
+        Some(tiling) => tile(
+            &plan,
+            tiling,
+            fiducials.as_ref(),
+            &svg,
+            &file,
+            &parameters,
+            &args.output,
+        )?,
+        None => {
+            if let Some(fiducials) = &fiducials {
+                add_fiducials(fiducials, &mut plan);
+            }
+            export(&plan, &svg, &file, &parameters, &args.output)?
+        }
+    }
+    Ok(())
+}
+
+/// Whether the output can be scribed straight into the file, without holding the plan in
+/// memory: an SVG without any of the options that work on the whole plan
+fn streams(args: &Args, file: &ConfigFile, config: &ScriberConfig, fiducials: bool) -> bool {
+    let svg = Path::new(&args.output)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        == Some("svg");
+    let clip = args.plate.is_some() || !args.hole.is_empty() || !file.plate.is_empty();
+    let grouped = args.group_by.is_some() || config.grouping.is_some();
+    svg && !clip
+        && !grouped
+        && !fiducials
+        && !args.merge_circles
+        && !args.optimize_toolpath
+        && args.tile.is_none()
+        && args.depth_levels.is_none()
+        && !args.compass_kit
+}
+
+/// Scribe the points into the SVG output a chunk at a time, applying the options that work
+/// primitive by primitive to each chunk
+fn stream(
+    args: &Args,
+    scriber: &Scriber,
+    points: &[Vec3],
+    svg: &SvgExporter,
+    provenance: Provenance,
+    back_side: Option<&BackSide>,
+) -> Result<(), Box<dyn Error>> {
+    let mut lengths = ScribeLengths::default();
+    let each = |plan: &mut ScribePlan| {
+        if let Some(back_side) = back_side {
+            back_side.mirror(plan);
+        }
+        if args.strategy == Strategy::Arc {
+            let chunk = ScribeLengths::of(plan);
+            lengths.full += chunk.full;
+            lengths.scribed += chunk.scribed;
+        }
+        if let Some(tolerance) = args.flatten {
+            plan.flatten(tolerance);
+        }
+    };
+    let writer = BufWriter::new(File::create(&args.output)?);
+    scriber
+        .scribe_to_with(points, svg, Some(provenance), each, writer)?
+        .flush()?;
+    if args.strategy == Strategy::Arc {
+        println!("{lengths}");
+    }
+    Ok(())
+}
+
+/// Snap the depths of the points of all groups to the same levels
+fn quantize(
+    groups: &mut [(String, Vec<Vec3>)],
+    levels: usize,
+    choice: LevelChoice,
+) -> Vec<DepthLevel> {
+    let method = match choice {
+        LevelChoice::Uniform => LevelMethod::Uniform,
+        LevelChoice::KMeans => LevelMethod::KMeans,
+    };
+    let mut points: Vec<Vec3> = groups
+        .iter()
+        .flat_map(|(_, points)| points.iter().copied())
+        .collect();
+    let levels = DepthQuantizer::new(levels, method).quantize(&mut points);
+    let mut quantized = points.as_slice();
+    for (_, points) in groups.iter_mut() {
+        let (group, rest) = quantized.split_at(points.len());
+        points.copy_from_slice(group);
+        quantized = rest;
+    }
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use glam::Vec2;
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_back_side_hole() {
+        let path =
+            std::env::temp_dir().join(format!("holocli-{}-back-side.json", std::process::id()));
+        let output = path.to_string_lossy().into_owned();
+        let arguments = [
+            "-i",
+            "../holoscribe/tests/icosahedron.obj",
+            "-c",
+            "100x80mm",
+            "--back-side",
+            "--hole",
+            "30,40,12",
+            "-o",
+            &output,
+        ];
+        let command_line = ["holocli"].into_iter().chain(arguments);
+        run(
+            Args::try_parse_from(command_line).unwrap(),
+            Vec::new(),
+            None,
+        )
+        .unwrap();
+        let plan: ScribePlan = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_file(path).unwrap();
+
+        // The hole is given as seen from the front, so on the back it is on the other side
+        let points: Vec<Vec2> = plan
+            .primitives
+            .iter()
+            .flat_map(|primitive| primitive.shape.to_polyline(0.01))
+            .map(|point| plan.to_mm(point))
+            .collect();
+        let inside = |center: Vec2| {
+            points
+                .iter()
+                .filter(|point| point.distance(center) < 11.5)
+                .count()
+        };
+        assert_eq!(inside(Vec2::new(70.0, 40.0)), 0);
+        assert!(inside(Vec2::new(30.0, 40.0)) > 0);
+    }
+
+    #[test]
+    fn test_depth_level_layers() {
+        let dir = std::env::temp_dir().join(format!("holocli-{}-levels", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for extension in ["svg", "dxf"] {
+            let output = dir.join(format!("levels.{extension}"));
+            let output = output.to_string_lossy();
+            let command_line = [
+                "holocli",
+                "-i",
+                "../holoscribe/tests/icosahedron.obj",
+                "-c",
+                "100x80mm",
+                "--depth-levels",
+                "3",
+                "-o",
+                &output,
+            ];
+            run(
+                Args::try_parse_from(command_line).unwrap(),
+                Vec::new(),
+                None,
+            )
+            .unwrap();
+            let text = fs::read_to_string(output.as_ref()).unwrap();
+            let layers: Vec<&str> = match extension {
+                "svg" => text
+                    .split("inkscape:label=\"")
+                    .skip(1)
+                    .filter_map(|rest| rest.split('"').next())
+                    .collect(),
+                // The names in the layer table
+                _ => text
+                    .split("\nLAYER\n  2\n")
+                    .skip(1)
+                    .filter_map(|rest| rest.lines().next())
+                    .collect(),
+            };
+            assert_eq!(layers, ["level-1", "level-2", "level-3"], "{extension}");
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,233 @@
+use std::error::Error;
+use std::fs;
+use std::time::SystemTime;
+
+use clap::Parser;
+use sha2::{Digest, Sha256};
+
+use crate::cli::{Args, Regenerate};
+use crate::pipeline::run;
+use holoscribe::{export::Provenance, scriber::ScribePlan};
+
+/// Make the output again from the provenance written into a file by an earlier run
+pub fn regenerate(regenerate: Regenerate) -> Result<(), Box<dyn Error>> {
+    let text = String::from_utf8_lossy(&fs::read(&regenerate.file)?).into_owned();
+    let provenance = match regenerate.file.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_str::<ScribePlan>(&text)?.provenance,
+        Some("svg") => Provenance::from_text(&unescape_xml(&text)),
+        Some("hpgl" | "plt") => Provenance::from_text(&hpgl_comments(&text)),
+        _ => Provenance::from_text(&text),
+    }
+    .ok_or_else(|| format!("{} has no provenance", regenerate.file.display()))?;
+
+    println!(
+        "regenerating with {} from {}",
+        provenance.tool, provenance.timestamp
+    );
+    let files = [(&provenance.model, &provenance.model_hash)]
+        .into_iter()
+        .chain(provenance.files.iter().map(|(path, hash)| (path, hash)));
+    for (path, hash) in files {
+        if sha256(path)? != *hash {
+            eprintln!("warning: {path} changed since it was used, the output will differ");
+        }
+    }
+    let output = regenerate.output.unwrap_or(provenance.output);
+    let command_line = ["holocli".to_string()]
+        .into_iter()
+        .chain(provenance.arguments.iter().cloned())
+        .chain(["--output".to_string(), output]);
+    let args = Args::try_parse_from(command_line)?;
+    run(args, provenance.arguments, provenance.config)
+}
+
+/// Where the output of this run came from
+pub fn provenance(
+    args: &Args,
+    arguments: Vec<String>,
+    config: Option<String>,
+) -> Result<Provenance, Box<dyn Error>> {
+    let files = referenced_files(args)
+        .into_iter()
+        .map(|path| Ok((path.to_string(), sha256(path)?)))
+        .collect::<Result<_, Box<dyn Error>>>()?;
+    Ok(Provenance {
+        model: args.input.clone(),
+        model_hash: sha256(&args.input)?,
+        files,
+        arguments,
+        output: args.output.clone(),
+        config,
+        tool: format!("holocli {}", env!("CARGO_PKG_VERSION")),
+        timestamp: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+    })
+}
+
+/// Files other than the model the output depends on: the calibration table and the plate
+/// outline
+fn referenced_files(args: &Args) -> Vec<&str> {
+    let plate = args
+        .plate
+        .as_deref()
+        .filter(|plate| !matches!(*plate, "rectangle" | "circle"));
+    args.depth_table
+        .as_deref()
+        .into_iter()
+        .chain(plate)
+        .collect()
+}
+
+/// SHA-256 of a file in hex
+fn sha256(path: &str) -> Result<String, Box<dyn Error>> {
+    let contents = fs::read(path).map_err(|error| format!("can't read {path}: {error}"))?;
+    let digest = Sha256::digest(contents);
+    Ok(digest.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+/// Command line arguments without the output option and its value, which are recorded
+/// separately in the provenance
+pub fn without_output(arguments: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut arguments = arguments.into_iter();
+    let mut kept = Vec::new();
+    while let Some(argument) = arguments.next() {
+        if argument == "-o" || argument == "--output" {
+            arguments.next();
+        } else if !(argument.starts_with("--output=") || argument.starts_with("-o")) {
+            kept.push(argument);
+        }
+    }
+    kept
+}
+
+/// The text of the `CO"…";` comment instructions of HPGL, a line each
+fn hpgl_comments(text: &str) -> String {
+    text.lines()
+        .filter_map(|line| line.strip_prefix("CO\"")?.strip_suffix("\";"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Undo the escaping of text in XML
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_without_output() {
+        let arguments = [
+            "-i",
+            "m.obj",
+            "-o",
+            "m.svg",
+            "--output=m.gcode",
+            "-om.dxf",
+            "-c",
+            "1m",
+        ];
+        assert_eq!(
+            without_output(arguments.map(String::from)),
+            ["-i", "m.obj", "-c", "1m"]
+        );
+    }
+
+    #[test]
+    fn test_unescape_xml() {
+        assert_eq!(
+            unescape_xml("&lt;a href=&quot;x&amp;y&quot;&gt;&apos;"),
+            "<a href=\"x&y\">'"
+        );
+        assert_eq!(unescape_xml("&amp;lt;"), "&lt;");
+    }
+
+    #[test]
+    fn test_hpgl_comments() {
+        let hpgl = "IN;\nCO\"provenance model: lamp.obj\";\nSP1;\nCO\"a 'b'\";\n";
+        assert_eq!(hpgl_comments(hpgl), "provenance model: lamp.obj\na 'b'");
+    }
+
+    #[test]
+    fn test_sha256() {
+        let path = std::env::temp_dir().join(format!("holocli-{}-sha256", std::process::id()));
+        fs::write(&path, "abc").unwrap();
+        assert_eq!(
+            sha256(&path.to_string_lossy()).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        fs::remove_file(&path).unwrap();
+        let error = sha256(&path.to_string_lossy()).unwrap_err();
+        assert!(error.to_string().contains(path.to_string_lossy().as_ref()));
+    }
+
+    #[test]
+    fn test_regenerate() {
+        let dir = std::env::temp_dir().join(format!("holocli-{}-regenerate", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let output = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let scribe = |output: &str| {
+            let arguments = [
+                "-i",
+                "../holoscribe/tests/icosahedron.obj",
+                "-c",
+                "100x80mm",
+            ];
+            let options = ["--strategy", "arc", "--margin-mm", "5", "-o", output];
+            let arguments: Vec<String> = arguments
+                .into_iter()
+                .chain(options)
+                .map(String::from)
+                .collect();
+            let args = Args::try_parse_from(
+                ["holocli"]
+                    .into_iter()
+                    .chain(arguments.iter().map(String::as_str)),
+            )
+            .unwrap();
+            run(
+                args,
+                without_output(arguments),
+                Some("[style]\nstroke = \"red\"".to_string()),
+            )
+            .unwrap();
+        };
+        let plan = |path: &str| -> ScribePlan {
+            serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+        };
+
+        // The provenance read back from the SVG makes the same plan as the options did
+        scribe(&output("first.json"));
+        scribe(&output("first.svg"));
+        let svg = fs::read_to_string(output("first.svg")).unwrap();
+        let provenance = Provenance::from_text(&unescape_xml(&svg)).unwrap();
+        assert_eq!(
+            provenance.config.as_deref(),
+            Some("[style]\nstroke = \"red\"")
+        );
+        regenerate(Regenerate {
+            file: output("first.svg").into(),
+            output: Some(output("second.json")),
+        })
+        .unwrap();
+        let (first, second) = (plan(&output("first.json")), plan(&output("second.json")));
+        assert_eq!(first.primitives, second.primitives);
+        let recorded = second.provenance.unwrap();
+        assert_eq!(recorded.arguments, provenance.arguments);
+        assert_eq!(recorded.output, output("second.json"));
+
+        // And so does the one in the JSON plan
+        regenerate(Regenerate {
+            file: output("second.json").into(),
+            output: Some(output("third.json")),
+        })
+        .unwrap();
+        assert_eq!(plan(&output("third.json")).primitives, first.primitives);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,40 @@
+// How much of scribing is spent laying the model out on the canvas. Radii depend on the
+// scale, so a contain fit scribes every point once per layout round to find the extent,
+// where a fixed scale needs a single round
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use glam::Vec3;
+use holoscribe::scriber::{CircleScriber, Fit, Scriber, ScriberConfig};
+
+/// Points on a wavy surface, a few thousand like a small model
+fn points() -> Vec<Vec3> {
+    (0..100)
+        .flat_map(|i| (0..100).map(move |j| (i as f32 / 10.0, j as f32 / 10.0)))
+        .map(|(x, y)| Vec3::new(x, y, (x * 0.7).sin() + (y * 1.3).cos()))
+        .collect()
+}
+
+fn layout(c: &mut Criterion) {
+    let points = points();
+    let scriber = |fit| {
+        let config = ScriberConfig {
+            fit,
+            ..Default::default()
+        };
+        Scriber::with_config(CircleScriber::new(), (200, 200), config)
+    };
+    let contain = scriber(Fit::Contain);
+    let fixed = scriber(Fit::Fixed { mm_per_unit: 15.0 });
+    c.bench_function("plan, contain fit", |b| {
+        b.iter(|| contain.plan(black_box(&points)))
+    });
+    c.bench_function("plan, fixed scale", |b| {
+        b.iter(|| fixed.plan(black_box(&points)))
+    });
+    c.bench_function("stream, contain fit", |b| {
+        b.iter(|| contain.scribe_to(black_box(&points), std::io::sink()))
+    });
+}
+
+criterion_group!(benches, layout);
+criterion_main!(benches);
@@ -0,0 +1,227 @@
+use serde::Serialize;
+use std::error::Error;
+use std::io::{self, Write};
+use svg::node::element::path::Data;
+use svg::node::element::{Path, Text};
+use svg::Document;
+
+use crate::scriber::{ScribePlan, Shape};
+
+type Num = f32;
+
+/// One compass setting: where to put the point and what to scribe from there. Positions are
+/// in mm from the top left corner of the canvas, angles in degrees clockwise from 3 o'clock.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CompassEntry {
+    pub id: usize,
+    #[serde(rename = "x_mm")]
+    pub x: Num,
+    #[serde(rename = "y_mm")]
+    pub y: Num,
+    #[serde(rename = "radius_mm")]
+    pub radius: Num,
+    #[serde(rename = "start_deg")]
+    pub start: Num,
+    #[serde(rename = "end_deg")]
+    pub end: Num,
+}
+
+/// Everything needed to scribe a plan by hand with a compass: a table of circle centers and
+/// radii, and a template marking the centers with their numbers.
+///
+/// Entries are sorted by radius, so the compass is set once for each run of equal radii.
+/// Only circles and arcs can be scribed with a compass, other shapes are left out.
+pub struct CompassKit {
+    pub entries: Vec<CompassEntry>,
+    /// Number of primitives that aren't circles or arcs
+    pub skipped: usize,
+    radius_step: Num,
+    canvas_size: (Num, Num),
+}
+
+impl CompassKit {
+    /// Radii that round to the same multiple of `radius_step` mm share a compass setting, and
+    /// are ordered top to bottom, left to right within it
+    pub fn new(plan: &ScribePlan, radius_step: Num) -> Self {
+        let scale = plan.mm_per_unit();
+        let mut skipped = 0;
+        let mut entries = Vec::new();
+        for primitive in plan.in_order() {
+            let (center, radius, start, sweep) = match primitive.shape {
+                Shape::Circle { center, radius } => (center, radius, 0.0, 360.0),
+                Shape::Arc {
+                    center,
+                    radius,
+                    start,
+                    sweep,
+                } => {
+                    // Always scribe clockwise on the plate
+                    let start = if sweep < 0.0 { start + sweep } else { start };
+                    (
+                        center,
+                        radius,
+                        start.to_degrees().rem_euclid(360.0),
+                        sweep.abs().to_degrees(),
+                    )
+                }
+                _ => {
+                    skipped += 1;
+                    continue;
+                }
+            };
+            let center = plan.to_mm(center);
+            entries.push(CompassEntry {
+                id: 0,
+                x: center.x,
+                y: center.y,
+                radius: radius * scale,
+                start,
+                end: start + sweep,
+            });
+        }
+
+        entries.sort_by(|a, b| {
+            setting(a, radius_step)
+                .total_cmp(&setting(b, radius_step))
+                .then(a.y.total_cmp(&b.y))
+                .then(a.x.total_cmp(&b.x))
+        });
+        for (index, entry) in entries.iter_mut().enumerate() {
+            entry.id = index + 1;
+        }
+
+        Self {
+            entries,
+            skipped,
+            radius_step,
+            canvas_size: plan.canvas_size,
+        }
+    }
+
+    /// Number of times the compass has to be set to a new radius
+    pub fn settings(&self) -> usize {
+        let changes = self
+            .entries
+            .windows(2)
+            .filter(|pair| {
+                setting(&pair[0], self.radius_step) != setting(&pair[1], self.radius_step)
+            })
+            .count();
+        changes + usize::from(!self.entries.is_empty())
+    }
+
+    pub fn write_csv(&self, out: &mut dyn Write) -> Result<(), Box<dyn Error>> {
+        let mut writer = csv::Writer::from_writer(out);
+        for entry in &self.entries {
+            writer.serialize(entry)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn write_markdown(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(
+            out,
+            "| id | x (mm) | y (mm) | radius (mm) | start (deg) | end (deg) |"
+        )?;
+        writeln!(out, "|---:|---:|---:|---:|---:|---:|")?;
+        for entry in &self.entries {
+            writeln!(
+                out,
+                "| {} | {:.2} | {:.2} | {:.2} | {:.1} | {:.1} |",
+                entry.id, entry.x, entry.y, entry.radius, entry.start, entry.end
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Write the template made by `document`
+    pub fn write_svg(&self, out: &mut dyn Write) -> io::Result<()> {
+        svg::write(out, &self.document())
+    }
+
+    /// The template: a numbered crosshair at every center, in mm
+    pub fn document(&self) -> Document {
+        let (width, height) = self.canvas_size;
+        let size = 1.5;
+        let mut data = Data::new();
+        let mut doc = Document::new()
+            .set("width", format!("{width}mm"))
+            .set("height", format!("{height}mm"))
+            .set("viewBox", (0.0, 0.0, width, height));
+        for entry in &self.entries {
+            data = data
+                .move_to((entry.x - size, entry.y))
+                .horizontal_line_by(2.0 * size)
+                .move_to((entry.x, entry.y - size))
+                .vertical_line_by(2.0 * size);
+            let label = Text::new()
+                .set("x", entry.x + 0.5)
+                .set("y", entry.y - 0.5)
+                .set("font-size", 2)
+                .set("font-family", "sans-serif")
+                .add(svg::node::Text::new(entry.id.to_string()));
+            doc = doc.add(label);
+        }
+        let crosshairs = Path::new()
+            .set("d", data)
+            .set("stroke", "black")
+            .set("stroke-width", 0.1)
+            .set("fill", "none");
+        doc.add(crosshairs)
+    }
+}
+
+/// The compass setting of an entry, as a multiple of `radius_step`
+fn setting(entry: &CompassEntry, radius_step: Num) -> Num {
+    (entry.radius / radius_step).round()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scriber::{ImageKind, Rect};
+    use glam::Vec2;
+    use std::f32::consts::FRAC_PI_2;
+
+    #[test]
+    fn test_compass_table() {
+        // 1 unit is 10mm, the canvas starts at (0, 0)
+        let mut plan = ScribePlan::new(Rect::new(0.0, 0.0, 10.0, 10.0), (100.0, 100.0));
+        let circle = |x, y, radius| Shape::Circle {
+            center: Vec2::new(x, y),
+            radius,
+        };
+        plan.push(circle(1.0, 5.0, 2.0), 1.0, ImageKind::Virtual, "circles");
+        plan.push(circle(1.0, 1.0, 2.0), 1.0, ImageKind::Virtual, "circles");
+        let arc = Shape::Arc {
+            center: Vec2::new(5.0, 5.0),
+            radius: 1.0,
+            start: 0.0,
+            sweep: -FRAC_PI_2,
+        };
+        plan.push(arc, -1.0, ImageKind::Real, "real-circles");
+        let quadratic = Shape::Quadratic {
+            start: Vec2::ZERO,
+            control: Vec2::ONE,
+            end: Vec2::X,
+        };
+        plan.push(quadratic, 1.0, ImageKind::Virtual, "circles");
+
+        let kit = CompassKit::new(&plan, 0.1);
+        assert_eq!(kit.skipped, 1);
+        assert_eq!(kit.settings(), 2);
+        let mut out = Vec::new();
+        kit.write_markdown(&mut out).unwrap();
+        let table = String::from_utf8(out).unwrap();
+        let rows: Vec<&str> = table.lines().skip(2).collect();
+        assert_eq!(
+            rows,
+            [
+                "| 1 | 50.00 | 50.00 | 10.00 | 270.0 | 360.0 |",
+                "| 2 | 10.00 | 10.00 | 20.00 | 0.0 | 360.0 |",
+                "| 3 | 10.00 | 50.00 | 20.00 | 0.0 | 360.0 |",
+            ]
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::f32::consts::TAU;
+use std::io::{self, Write};
+
+use super::{machine_fiducials, machine_point, Exporter, Provenance};
+use crate::scriber::{Primitive, ScribePlan, Shape};
+
+type Num = f32;
+
+/// Settings for `DxfExporter`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DxfConfig {
+    /// Split each DXF layer into bands of this many mm of depth, named
+    /// `<layer>-depth-<n>` for depths from `n * depth_band` to `(n + 1) * depth_band`.
+    /// `None` keeps the layers as they are
+    pub depth_band: Option<Num>,
+    /// Maximum deviation in mm of the polylines replacing curves DXF R12 can't describe
+    pub tolerance: Num,
+}
+
+impl Default for DxfConfig {
+    fn default() -> Self {
+        Self {
+            depth_band: None,
+            tolerance: 0.01,
+        }
+    }
+}
+
+/// Writes a plan as an R12 DXF drawing in millimeters, which R12 can only say in a comment.
+/// Circles and arcs become `CIRCLE` and
+/// `ARC` entities, everything else `POLYLINE`s. Each group of the plan gets a DXF layer, and
+/// ungrouped primitives one per plan layer.
+pub struct DxfExporter {
+    config: DxfConfig,
+}
+
+impl DxfExporter {
+    pub fn new(config: DxfConfig) -> Self {
+        Self { config }
+    }
+
+    /// Name of the DXF layer a primitive goes into: its group, or else its layer
+    fn layer(&self, primitive: &Primitive) -> String {
+        let name = primitive.group.as_ref().unwrap_or(&primitive.layer);
+        // R12 layer names only have letters, digits, `$`, `-` and `_`
+        let name: String = name
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '$' | '-' | '_' => c,
+                _ => '_',
+            })
+            .collect();
+        match self.config.depth_band {
+            Some(band) => {
+                let index = (primitive.depth / band).floor() as i64;
+                format!("{name}-depth-{index}")
+            }
+            None => name,
+        }
+    }
+
+    fn tables(&self, out: &mut dyn Write, layers: &BTreeSet<String>) -> io::Result<()> {
+        group(out, 0, "SECTION")?;
+        group(out, 2, "TABLES")?;
+        group(out, 0, "TABLE")?;
+        group(out, 2, "LAYER")?;
+        group(out, 70, layers.len())?;
+        for (index, layer) in layers.iter().enumerate() {
+            group(out, 0, "LAYER")?;
+            group(out, 2, layer)?;
+            group(out, 70, 0)?;
+            // Cycle through the standard colors, skipping white
+            group(out, 62, index % 6 + 1)?;
+            group(out, 6, "CONTINUOUS")?;
+        }
+        group(out, 0, "ENDTAB")?;
+        group(out, 0, "ENDSEC")
+    }
+
+    fn entity(
+        &self,
+        out: &mut dyn Write,
+        plan: &ScribePlan,
+        primitive: &Primitive,
+    ) -> io::Result<()> {
+        let layer = self.layer(primitive);
+        let scale = plan.mm_per_unit();
+        match &primitive.shape {
+            Shape::Circle { center, radius } => {
+                circle(out, &layer, machine_point(plan, *center), radius * scale)
+            }
+            // Full turns, like circles entered away from their usual starting point
+            Shape::Arc {
+                center,
+                radius,
+                sweep,
+                ..
+            } if sweep.abs() >= TAU => {
+                circle(out, &layer, machine_point(plan, *center), radius * scale)
+            }
+            Shape::Arc {
+                center,
+                radius,
+                start,
+                sweep,
+            } => {
+                // y points down in the plan and up in the drawing, which mirrors the angles.
+                // DXF arcs always run counter-clockwise from the start to the end angle
+                let (a, b) = (-start, -(start + sweep));
+                let (from, to) = (a.min(b), a.max(b));
+                group(out, 0, "ARC")?;
+                group(out, 8, layer)?;
+                point(out, 10, machine_point(plan, *center))?;
+                group(out, 40, num(radius * scale))?;
+                group(out, 50, num(from.to_degrees().rem_euclid(360.0)))?;
+                group(out, 51, num(to.to_degrees().rem_euclid(360.0)))
+            }
+            shape => {
+                group(out, 0, "POLYLINE")?;
+                group(out, 8, &layer)?;
+                group(out, 66, 1)?;
+                point(out, 10, Vec2::ZERO)?;
+                group(out, 70, 0)?;
+                for vertex in shape.to_polyline(self.config.tolerance / scale) {
+                    group(out, 0, "VERTEX")?;
+                    group(out, 8, &layer)?;
+                    point(out, 10, machine_point(plan, vertex))?;
+                }
+                group(out, 0, "SEQEND")?;
+                group(out, 8, layer)
+            }
+        }
+    }
+}
+
+impl Exporter for DxfExporter {
+    fn export(&self, plan: &ScribePlan, out: &mut dyn Write) -> io::Result<()> {
+        for line in plan.provenance.iter().flat_map(Provenance::lines) {
+            group(out, 999, line)?;
+        }
+        for fiducial in machine_fiducials(plan) {
+            group(out, 999, fiducial)?;
+        }
+        // R12 has no header variable for the units, $INSUNITS and $MEASUREMENT came later
+        group(out, 999, "units: mm")?;
+        group(out, 0, "SECTION")?;
+        group(out, 2, "HEADER")?;
+        group(out, 9, "$ACADVER")?;
+        group(out, 1, "AC1009")?;
+        group(out, 9, "$EXTMIN")?;
+        point(out, 10, Vec2::ZERO)?;
+        group(out, 9, "$EXTMAX")?;
+        point(out, 10, Vec2::new(plan.canvas_size.0, plan.canvas_size.1))?;
+        group(out, 0, "ENDSEC")?;
+
+        let layers = plan.primitives.iter().map(|p| self.layer(p)).collect();
+        self.tables(out, &layers)?;
+
+        group(out, 0, "SECTION")?;
+        group(out, 2, "ENTITIES")?;
+        for (_, primitives) in plan.groups() {
+            for primitive in primitives {
+                self.entity(out, plan, primitive)?;
+            }
+        }
+        group(out, 0, "ENDSEC")?;
+        group(out, 0, "EOF")
+    }
+}
+
+/// Write a group code and its value
+fn group(out: &mut dyn Write, code: u16, value: impl std::fmt::Display) -> io::Result<()> {
+    writeln!(out, "{code:>3}\n{value}")
+}
+
+/// Write a 2D point as the x, y and z groups starting at `code`
+fn point(out: &mut dyn Write, code: u16, point: Vec2) -> io::Result<()> {
+    group(out, code, num(point.x))?;
+    group(out, code + 10, num(point.y))?;
+    group(out, code + 20, "0.0")
+}
+
+fn circle(out: &mut dyn Write, layer: &str, center: Vec2, radius: Num) -> io::Result<()> {
+    group(out, 0, "CIRCLE")?;
+    group(out, 8, layer)?;
+    point(out, 10, center)?;
+    group(out, 40, num(radius))
+}
+
+fn num(value: Num) -> String {
+    // Adding zero turns -0 into 0
+    format!("{:.4}", value + 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scriber::{ImageKind, Rect};
+    use std::f32::consts::FRAC_PI_2;
+
+    #[test]
+    fn test_dxf_entities() {
+        // 1 unit is 10mm, the canvas starts at (0, 0)
+        let mut plan = ScribePlan::new(Rect::new(0.0, 0.0, 10.0, 10.0), (100.0, 100.0));
+        let circle = Shape::Circle {
+            center: Vec2::new(5.0, 2.0),
+            radius: 1.0,
+        };
+        plan.push(circle, 1.5, ImageKind::Virtual, "circles");
+        // A quarter from +x towards +y in the plan is the quarter below +x in the drawing
+        let arc = Shape::Arc {
+            center: Vec2::new(5.0, 5.0),
+            radius: 1.0,
+            start: 0.0,
+            sweep: FRAC_PI_2,
+        };
+        plan.push(arc, 0.5, ImageKind::Virtual, "circles");
+
+        let config = DxfConfig {
+            depth_band: Some(1.0),
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        DxfExporter::new(config).export(&plan, &mut out).unwrap();
+        let dxf = String::from_utf8(out).unwrap();
+        assert!(dxf.contains(
+            "  0\nCIRCLE\n  8\ncircles-depth-1\n 10\n50.0000\n 20\n80.0000\n 30\n0.0\n 40\n10.0000\n"
+        ));
+        assert!(dxf.contains("  0\nARC\n  8\ncircles-depth-0\n"));
+        assert!(dxf.contains(" 50\n270.0000\n 51\n0.0000\n"));
+        assert!(dxf.contains("  2\nLAYER\n 70\n2\n"));
+        assert!(dxf.ends_with("  0\nEOF\n"));
+        // Nothing R12 readers don't know in the header
+        assert!(dxf.starts_with("999\nunits: mm\n"));
+        assert!(!dxf.contains("$INSUNITS") && !dxf.contains("$MEASUREMENT"));
+    }
+}
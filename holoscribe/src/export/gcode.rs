@@ -0,0 +1,465 @@
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::f32::consts::PI;
+use std::io::{self, Write};
+
+use super::{machine_point, Exporter, Provenance};
+use crate::scriber::{Fiducial, Primitive, ScribePlan, Shape};
+
+type Num = f32;
+
+/// Radius in mm under which circles and arcs are left out. Their center would be written at
+/// their start, which controllers reject (GRBL with error 33), and there is nothing to scribe
+const MIN_ARC_RADIUS: Num = 0.001;
+
+/// The flavor of G-code written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dialect {
+    /// GRBL 1.1, which has no tool changer: tool changes pause the program with `M0`
+    Grbl,
+    /// LinuxCNC and other RS274NGC controllers, which change tools with `T<n> M6`
+    LinuxCnc,
+}
+
+/// Which point of the canvas is the work origin (X0 Y0)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkOrigin {
+    BottomLeft,
+    TopLeft,
+    Center,
+}
+
+/// What the intensity of a primitive controls on the machine
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum Modulation {
+    /// Nothing, every primitive is scribed the same
+    None,
+    /// The plunge depth, from `min_depth` at intensity 0 to `plunge_depth` at intensity 1
+    Depth { min_depth: Num },
+    /// Spindle speed or laser power, written as an `S` word with every plunge, from
+    /// `min_power` at intensity 0 to `max_power` at intensity 1. Without a `spindle_speed`
+    /// the program turns the spindle or laser on at power 0 to begin with, with `M4` for
+    /// GRBL's dynamic laser power and `M3` otherwise, as `S` words alone do nothing
+    Power { min_power: Num, max_power: Num },
+}
+
+/// Machine settings for `GcodeExporter`. Distances are in mm, rates in mm/min.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GcodeConfig {
+    pub dialect: Dialect,
+    pub origin: WorkOrigin,
+    /// Extra offset of the canvas from the work origin
+    pub offset: (Num, Num),
+    /// Height the tool travels at between scribes
+    pub safe_z: Num,
+    /// How deep below Z0 the tool scribes
+    pub plunge_depth: Num,
+    pub feed_rate: Num,
+    pub plunge_rate: Num,
+    /// Seconds to wait after plunging, 0 for none
+    pub dwell: Num,
+    /// Spindle speed, for rotating tools. `None` leaves the spindle off, unless the
+    /// modulation sets the power
+    pub spindle_speed: Option<Num>,
+    /// Tool number per group or layer, with groups taking precedence. Primitives without an
+    /// entry use tool 1, and a tool change block is written whenever the tool changes.
+    /// Without any entries no tool changes are written
+    pub tools: BTreeMap<String, u32>,
+    /// Pause the program with `M0` before every group after the first, to check the work
+    /// or skip a pass
+    pub pause_between_groups: bool,
+    /// What the intensity of each primitive controls
+    pub modulation: Modulation,
+    /// Maximum deviation in mm of the line segments replacing curves that G-code can't
+    /// describe as arcs
+    pub tolerance: Num,
+}
+
+impl Default for GcodeConfig {
+    fn default() -> Self {
+        Self {
+            dialect: Dialect::Grbl,
+            origin: WorkOrigin::BottomLeft,
+            offset: (0.0, 0.0),
+            safe_z: 5.0,
+            plunge_depth: 0.2,
+            feed_rate: 600.0,
+            plunge_rate: 100.0,
+            dwell: 0.0,
+            spindle_speed: None,
+            tools: BTreeMap::new(),
+            pause_between_groups: false,
+            modulation: Modulation::None,
+            tolerance: 0.01,
+        }
+    }
+}
+
+/// Writes a plan as a G-code program for CNC scribing rigs. Circles and arcs become
+/// `G2`/`G3` moves, everything else is scribed as line segments. Circles and arcs without a
+/// radius, and arcs so short that their ends are written at the same position, are left out.
+pub struct GcodeExporter {
+    config: GcodeConfig,
+}
+
+impl GcodeExporter {
+    pub fn new(config: GcodeConfig) -> Self {
+        Self { config }
+    }
+
+    /// Position of a plan point in machine coordinates
+    fn position(&self, plan: &ScribePlan, point: Vec2) -> Vec2 {
+        let (width, height) = plan.canvas_size;
+        let origin = match self.config.origin {
+            WorkOrigin::BottomLeft => Vec2::ZERO,
+            WorkOrigin::TopLeft => Vec2::new(0.0, height),
+            WorkOrigin::Center => Vec2::new(width, height) / 2.0,
+        };
+        machine_point(plan, point) - origin + Vec2::from(self.config.offset)
+    }
+
+    fn tool(&self, primitive: &Primitive) -> Option<u32> {
+        if self.config.tools.is_empty() {
+            return None;
+        }
+        let group = primitive
+            .group
+            .as_ref()
+            .and_then(|group| self.config.tools.get(group));
+        Some(
+            *group
+                .or(self.config.tools.get(&primitive.layer))
+                .unwrap_or(&1),
+        )
+    }
+
+    /// The command turning the spindle or laser on, if the program uses it
+    fn spindle_on(&self) -> Option<String> {
+        match (self.config.spindle_speed, self.config.modulation) {
+            (Some(speed), _) => Some(format!("M3 S{}", num(speed))),
+            (None, Modulation::Power { .. }) => Some(match self.config.dialect {
+                Dialect::Grbl => "M4 S0 ; power set with every plunge".to_string(),
+                Dialect::LinuxCnc => "M3 S0 ; speed set with every plunge".to_string(),
+            }),
+            (None, _) => None,
+        }
+    }
+
+    fn start_spindle(&self, out: &mut dyn Write) -> io::Result<()> {
+        if let Some(command) = self.spindle_on() {
+            writeln!(out, "{command}")?;
+        }
+        Ok(())
+    }
+
+    fn tool_change(&self, out: &mut dyn Write, tool: u32, layer: &str) -> io::Result<()> {
+        writeln!(out, "; layer {layer}")?;
+        writeln!(out, "G0 Z{}", num(self.config.safe_z))?;
+        if self.spindle_on().is_some() {
+            writeln!(out, "M5")?;
+        }
+        match self.config.dialect {
+            Dialect::Grbl => writeln!(out, "M0 ; change to tool {tool} and resume")?,
+            Dialect::LinuxCnc => writeln!(out, "T{tool} M6")?,
+        }
+        self.start_spindle(out)
+    }
+
+    /// Travel to `start`, plunge for a primitive of `intensity`, and leave the tool down there
+    fn plunge(&self, out: &mut dyn Write, start: Vec2, intensity: Num) -> io::Result<()> {
+        writeln!(out, "G0 X{} Y{}", num(start.x), num(start.y))?;
+        let depth = match self.config.modulation {
+            Modulation::Depth { min_depth } => {
+                min_depth + (self.config.plunge_depth - min_depth) * intensity
+            }
+            _ => self.config.plunge_depth,
+        };
+        write!(out, "G1 Z{} F{}", num(-depth), num(self.config.plunge_rate))?;
+        if let Modulation::Power {
+            min_power,
+            max_power,
+        } = self.config.modulation
+        {
+            write!(
+                out,
+                " S{}",
+                num(min_power + (max_power - min_power) * intensity)
+            )?;
+        }
+        writeln!(out)?;
+        if self.config.dwell > 0.0 {
+            writeln!(out, "G4 P{}", num(self.config.dwell))?;
+        }
+        Ok(())
+    }
+
+    /// Scribe from the start of an arc, where the tool is plunged, in arcs of at most half a
+    /// turn, so that no controller has to guess which way to go
+    fn arc(
+        &self,
+        out: &mut dyn Write,
+        plan: &ScribePlan,
+        center: Vec2,
+        radius: Num,
+        start: Num,
+        sweep: Num,
+    ) -> io::Result<()> {
+        let at = |t: Num| center + radius * Vec2::new(t.cos(), t.sin());
+        let center = self.position(plan, center);
+        let steps = (sweep.abs() / PI).ceil().max(1.0) as usize;
+        // y points down in the plan and up on the machine, which turns a positive sweep
+        // clockwise
+        let command = if sweep > 0.0 { "G2" } else { "G3" };
+        let mut from = self.position(plan, at(start));
+        for step in 1..=steps {
+            let to = self.position(plan, at(start + sweep * step as Num / steps as Num));
+            let offset = center - from;
+            writeln!(
+                out,
+                "{command} X{} Y{} I{} J{} F{}",
+                num(to.x),
+                num(to.y),
+                num(offset.x),
+                num(offset.y),
+                num(self.config.feed_rate)
+            )?;
+            from = to;
+        }
+        Ok(())
+    }
+
+    fn polyline(
+        &self,
+        out: &mut dyn Write,
+        plan: &ScribePlan,
+        points: &[Vec2],
+        intensity: Num,
+    ) -> io::Result<()> {
+        let Some((&first, rest)) = points.split_first() else {
+            return Ok(());
+        };
+        self.plunge(out, self.position(plan, first), intensity)?;
+        for &point in rest {
+            let to = self.position(plan, point);
+            writeln!(
+                out,
+                "G1 X{} Y{} F{}",
+                num(to.x),
+                num(to.y),
+                num(self.config.feed_rate)
+            )?;
+        }
+        Ok(())
+    }
+
+    fn primitive(
+        &self,
+        out: &mut dyn Write,
+        plan: &ScribePlan,
+        primitive: &Primitive,
+    ) -> io::Result<()> {
+        let start = self.position(plan, primitive.shape.endpoints().0);
+        match &primitive.shape {
+            Shape::Circle { radius, .. } | Shape::Arc { radius, .. }
+                if radius * plan.mm_per_unit() < MIN_ARC_RADIUS =>
+            {
+                return Ok(())
+            }
+            // Ends written at the same position would make controllers scribe a full circle
+            Shape::Arc { sweep, .. }
+                if sweep.abs() < PI
+                    && same_position(start, self.position(plan, primitive.shape.endpoints().1)) =>
+            {
+                return Ok(())
+            }
+            Shape::Circle { center, radius } => {
+                self.plunge(out, start, primitive.intensity)?;
+                self.arc(out, plan, *center, *radius, 0.0, 2.0 * PI)?
+            }
+            Shape::Arc {
+                center,
+                radius,
+                start: angle,
+                sweep,
+            } => {
+                self.plunge(out, start, primitive.intensity)?;
+                self.arc(out, plan, *center, *radius, *angle, *sweep)?
+            }
+            shape => {
+                let tolerance = self.config.tolerance / plan.mm_per_unit();
+                let points = shape.to_polyline(tolerance);
+                self.polyline(out, plan, &points, primitive.intensity)?
+            }
+        }
+        writeln!(out, "G0 Z{}", num(self.config.safe_z))
+    }
+}
+
+impl Exporter for GcodeExporter {
+    fn export(&self, plan: &ScribePlan, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "; scribe plan, {} primitives", plan.primitives.len())?;
+        for line in plan.provenance.iter().flat_map(Provenance::lines) {
+            writeln!(out, "; {line}")?;
+        }
+        for fiducial in &plan.fiducials {
+            let position = self.position(plan, plan.from_mm(fiducial.position));
+            writeln!(
+                out,
+                "; fiducial {}",
+                Fiducial {
+                    position,
+                    ..fiducial.clone()
+                }
+            )?;
+        }
+        writeln!(out, "G21 ; mm")?;
+        writeln!(out, "G90 ; absolute positions")?;
+        writeln!(out, "G17 ; arcs in the XY plane")?;
+        writeln!(out, "G0 Z{}", num(self.config.safe_z))?;
+        self.start_spindle(out)?;
+
+        let mut current_tool = None;
+        for (index, (name, primitives)) in plan.groups().into_iter().enumerate() {
+            if let Some(name) = name {
+                writeln!(out, "; group {name}")?;
+                // The tool is up at safe height after every primitive
+                if index > 0 && self.config.pause_between_groups {
+                    writeln!(out, "M0 ; start of group {name}")?;
+                }
+            }
+            for primitive in primitives {
+                let tool = self.tool(primitive);
+                if tool != current_tool {
+                    if let Some(tool) = tool {
+                        self.tool_change(out, tool, &primitive.layer)?;
+                    }
+                    current_tool = tool;
+                }
+                self.primitive(out, plan, primitive)?;
+            }
+        }
+
+        if self.spindle_on().is_some() {
+            writeln!(out, "M5")?;
+        }
+        writeln!(out, "M2")
+    }
+}
+
+/// Whether two positions are written as the same coordinates
+fn same_position(a: Vec2, b: Vec2) -> bool {
+    num(a.x) == num(b.x) && num(a.y) == num(b.y)
+}
+
+/// Format a coordinate with the precision controllers expect
+fn num(value: Num) -> String {
+    let formatted = format!("{value:.3}");
+    // Avoid writing -0.000
+    if formatted
+        .trim_start_matches('-')
+        .trim_matches(['0', '.'])
+        .is_empty()
+    {
+        "0.000".to_string()
+    } else {
+        formatted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scriber::{ImageKind, Rect};
+
+    #[test]
+    fn test_circle_as_arcs() {
+        // 1 unit is 10mm, the canvas starts at (0, 0)
+        let mut plan = ScribePlan::new(Rect::new(0.0, 0.0, 10.0, 10.0), (100.0, 100.0));
+        let circle = Shape::Circle {
+            center: Vec2::new(5.0, 5.0),
+            radius: 1.0,
+        };
+        plan.push(circle, 1.0, ImageKind::Virtual, "circles");
+
+        let mut out = Vec::new();
+        GcodeExporter::new(GcodeConfig::default())
+            .export(&plan, &mut out)
+            .unwrap();
+        let gcode = String::from_utf8(out).unwrap();
+        assert!(gcode.contains("G0 X60.000 Y50.000\nG1 Z-0.200 F100.000\n"));
+        assert!(gcode.contains("G2 X40.000 Y50.000 I-10.000 J0.000 F600.000\n"));
+        assert!(gcode.contains("G2 X60.000 Y50.000 I10.000 J0.000 F600.000\n"));
+        assert!(gcode.ends_with("G0 Z5.000\nM2\n"));
+    }
+
+    #[test]
+    fn test_skips_arcs_without_radius() {
+        // Points on the plate get circles of radius 0, which would be written as I0 J0
+        let mut plan = ScribePlan::new(Rect::new(0.0, 0.0, 10.0, 10.0), (100.0, 100.0));
+        let circle = Shape::Circle {
+            center: Vec2::new(5.0, 5.0),
+            radius: 0.0,
+        };
+        plan.push(circle, 0.0, ImageKind::Virtual, "circles");
+        let arc = Shape::Arc {
+            center: Vec2::new(5.0, 5.0),
+            radius: 1.0,
+            start: 0.0,
+            sweep: 0.0,
+        };
+        plan.push(arc, 1.0, ImageKind::Virtual, "circles");
+        // A sweep that isn't quite 0 still ends where it starts once rounded
+        let arc = Shape::Arc {
+            center: Vec2::new(5.0, 5.0),
+            radius: 1.0,
+            start: 0.5,
+            sweep: 1e-6,
+        };
+        plan.push(arc, 1.0, ImageKind::Virtual, "circles");
+
+        let mut out = Vec::new();
+        GcodeExporter::new(GcodeConfig::default())
+            .export(&plan, &mut out)
+            .unwrap();
+        let gcode = String::from_utf8(out).unwrap();
+        assert!(!gcode.contains("G1 Z"));
+        assert!(!gcode.contains("\nG2 ") && !gcode.contains("\nG3 "));
+    }
+
+    #[test]
+    fn test_modulation() {
+        let mut plan = ScribePlan::new(Rect::new(0.0, 0.0, 10.0, 10.0), (100.0, 100.0));
+        let line = Shape::Polyline {
+            points: vec![Vec2::new(1.0, 1.0), Vec2::new(2.0, 1.0)],
+        };
+        plan.push(line, 1.0, ImageKind::Virtual, "lines");
+        plan.primitives[0].intensity = 0.5;
+
+        let gcode = |modulation| {
+            let mut out = Vec::new();
+            let config = GcodeConfig {
+                modulation,
+                ..Default::default()
+            };
+            GcodeExporter::new(config).export(&plan, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert!(gcode(Modulation::Depth { min_depth: 0.1 }).contains("G1 Z-0.150 F100.000\n"));
+        let power = Modulation::Power {
+            min_power: 200.0,
+            max_power: 1000.0,
+        };
+        let modulated = gcode(power);
+        assert!(modulated.contains("G1 Z-0.200 F100.000 S600.000\n"));
+        // The S words only take effect with the laser on
+        let laser_on = modulated.find("\nM4 S0").unwrap();
+        assert!(laser_on < modulated.find(" S600.000").unwrap());
+        assert!(modulated.ends_with("M5\nM2\n"));
+        assert!(!gcode(Modulation::None).contains("\nM4"));
+    }
+}
@@ -0,0 +1,218 @@
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use super::{machine_fiducials, machine_point, Exporter, Provenance};
+use crate::scriber::{Primitive, ScribePlan, Shape};
+
+type Num = f32;
+
+/// Plotter settings for `HpglExporter`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HpglConfig {
+    /// Plotter units per mm. Most plotters use 40 (0.025 mm per unit)
+    pub units_per_mm: Num,
+    /// Pen number per group or layer, with groups taking precedence. Primitives without an
+    /// entry use pen 1
+    pub pens: BTreeMap<String, u32>,
+    /// Write circles as `CI` and arcs as `AA` commands. Some cutters only support straight
+    /// lines, for those every curve is written as line segments instead
+    pub native_arcs: bool,
+    /// Maximum deviation in mm of the line segments replacing curves
+    pub tolerance: Num,
+    /// Write the provenance and the fiducials of the plan at the start, with the positions of
+    /// the fiducials in mm from the bottom left corner, as `CO` comment instructions. Only
+    /// for HP-GL/2 devices, HP-GL/1 plotters and cutters don't know `CO` and stop or
+    /// misbehave on it
+    pub comments: bool,
+}
+
+impl Default for HpglConfig {
+    fn default() -> Self {
+        Self {
+            units_per_mm: 40.0,
+            pens: BTreeMap::new(),
+            native_arcs: true,
+            tolerance: 0.05,
+            comments: false,
+        }
+    }
+}
+
+/// Writes a plan as HPGL for pen plotters and vinyl cutters
+pub struct HpglExporter {
+    config: HpglConfig,
+}
+
+impl HpglExporter {
+    pub fn new(config: HpglConfig) -> Self {
+        Self { config }
+    }
+
+    /// Position of a plan point in plotter units
+    fn position(&self, plan: &ScribePlan, point: Vec2) -> (i64, i64) {
+        let units = machine_point(plan, point) * self.config.units_per_mm;
+        (units.x.round() as i64, units.y.round() as i64)
+    }
+
+    fn pen(&self, primitive: &Primitive) -> u32 {
+        let group = primitive
+            .group
+            .as_ref()
+            .and_then(|group| self.config.pens.get(group));
+        *group
+            .or(self.config.pens.get(&primitive.layer))
+            .unwrap_or(&1)
+    }
+
+    fn polyline(&self, out: &mut dyn Write, plan: &ScribePlan, points: &[Vec2]) -> io::Result<()> {
+        let Some((&first, rest)) = points.split_first() else {
+            return Ok(());
+        };
+        let (x, y) = self.position(plan, first);
+        write!(out, "PU{x},{y};")?;
+        if !rest.is_empty() {
+            let points: Vec<String> = rest
+                .iter()
+                .map(|&point| {
+                    let (x, y) = self.position(plan, point);
+                    format!("{x},{y}")
+                })
+                .collect();
+            write!(out, "PD{};", points.join(","))?;
+        }
+        writeln!(out, "PU;")
+    }
+
+    fn primitive(
+        &self,
+        out: &mut dyn Write,
+        plan: &ScribePlan,
+        primitive: &Primitive,
+    ) -> io::Result<()> {
+        let scale = plan.mm_per_unit() * self.config.units_per_mm;
+        match &primitive.shape {
+            Shape::Circle { center, radius } if self.config.native_arcs => {
+                let (x, y) = self.position(plan, *center);
+                writeln!(out, "PU{x},{y};CI{};", (radius * scale).round() as i64)
+            }
+            Shape::Arc {
+                center,
+                radius,
+                start,
+                sweep,
+            } if self.config.native_arcs => {
+                let from = *center + *radius * Vec2::new(start.cos(), start.sin());
+                let (x, y) = self.position(plan, from);
+                let (cx, cy) = self.position(plan, *center);
+                // y points down in the plan and up on the plotter, which reverses the sweep
+                writeln!(
+                    out,
+                    "PU{x},{y};PD;AA{cx},{cy},{:.2};PU;",
+                    -sweep.to_degrees()
+                )
+            }
+            shape => {
+                let tolerance = self.config.tolerance / plan.mm_per_unit();
+                self.polyline(out, plan, &shape.to_polyline(tolerance))
+            }
+        }
+    }
+}
+
+impl Exporter for HpglExporter {
+    fn export(&self, plan: &ScribePlan, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "IN;")?;
+        if self.config.comments {
+            for line in plan.provenance.iter().flat_map(Provenance::lines) {
+                comment(out, &line)?;
+            }
+            for fiducial in machine_fiducials(plan) {
+                comment(out, &format!("fiducial {fiducial}"))?;
+            }
+        }
+        let mut current_pen = None;
+        for primitive in plan
+            .groups()
+            .into_iter()
+            .flat_map(|(_, primitives)| primitives)
+        {
+            let pen = self.pen(primitive);
+            if current_pen != Some(pen) {
+                writeln!(out, "SP{pen};")?;
+                current_pen = Some(pen);
+            }
+            self.primitive(out, plan, primitive)?;
+        }
+        writeln!(out, "PU;SP0;")
+    }
+}
+
+/// Write a `CO` comment instruction on a line of its own. Quotes would end it, so they are
+/// replaced
+fn comment(out: &mut dyn Write, text: &str) -> io::Result<()> {
+    writeln!(out, "CO\"{}\";", text.replace('"', "'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scriber::{Fiducial, FiducialKind, ImageKind, Rect};
+    use std::f32::consts::FRAC_PI_2;
+
+    #[test]
+    fn test_hpgl_commands() {
+        // 1 unit is 10mm, the canvas starts at (0, 0)
+        let mut plan = ScribePlan::new(Rect::new(0.0, 0.0, 10.0, 10.0), (100.0, 100.0));
+        let circle = Shape::Circle {
+            center: Vec2::new(5.0, 5.0),
+            radius: 1.0,
+        };
+        plan.push(circle, 1.0, ImageKind::Virtual, "circles");
+        let arc = Shape::Arc {
+            center: Vec2::new(5.0, 5.0),
+            radius: 1.0,
+            start: 0.0,
+            sweep: FRAC_PI_2,
+        };
+        plan.push(arc, -1.0, ImageKind::Real, "real-circles");
+
+        let config = HpglConfig {
+            pens: BTreeMap::from([("real-circles".to_string(), 2)]),
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        HpglExporter::new(config).export(&plan, &mut out).unwrap();
+        let hpgl = String::from_utf8(out).unwrap();
+        assert_eq!(
+            hpgl,
+            "IN;\nSP1;\nPU2000,2000;CI400;\nSP2;\nPU2400,2000;PD;AA2000,2000,-90.00;PU;\nPU;SP0;\n"
+        );
+
+        plan.provenance = Some(Provenance {
+            model: "lamp.obj".to_string(),
+            ..Default::default()
+        });
+        plan.fiducials.push(Fiducial {
+            kind: FiducialKind::CornerCross,
+            position: Vec2::new(6.0, 6.0),
+            size: 4.0,
+        });
+        let hpgl = |config: HpglConfig| {
+            let mut out = Vec::new();
+            HpglExporter::new(config).export(&plan, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        // Comments are HP-GL/2, so they have to be asked for
+        assert!(!hpgl(HpglConfig::default()).contains("CO"));
+        let comments = HpglConfig {
+            comments: true,
+            ..Default::default()
+        };
+        let hpgl = hpgl(comments);
+        assert!(hpgl.starts_with("IN;\nCO\"provenance model: lamp.obj\";\n"));
+        assert!(hpgl.contains("CO\"fiducial corner_cross at 6.00, 94.00 mm, 4.00 mm\";\n"));
+    }
+}
@@ -0,0 +1,46 @@
+mod compass;
+mod dxf;
+mod gcode;
+mod hpgl;
+mod pdf;
+mod provenance;
+mod svg;
+mod tiles;
+
+use glam::Vec2;
+use std::io::{self, Write};
+
+use crate::scriber::{Fiducial, ScribePlan};
+
+pub use self::compass::{CompassEntry, CompassKit};
+pub use self::dxf::{DxfConfig, DxfExporter};
+pub use self::gcode::{Dialect, GcodeConfig, GcodeExporter, Modulation, WorkOrigin};
+pub use self::hpgl::{HpglConfig, HpglExporter};
+pub use self::pdf::{PdfConfig, PdfExporter};
+pub use self::provenance::{Provenance, PROVENANCE_PREFIX};
+pub use self::svg::{SvgElements, SvgExporter};
+pub use self::tiles::TileIndex;
+
+/// Renders a `ScribePlan` in an output format
+pub trait Exporter {
+    fn export(&self, plan: &ScribePlan, out: &mut dyn Write) -> io::Result<()>;
+}
+
+/// Position of a plan point in millimeters from the bottom left corner of the canvas, with
+/// y pointing up as machines and CAD formats expect
+pub(crate) fn machine_point(plan: &ScribePlan, point: Vec2) -> Vec2 {
+    let mm = plan.to_mm(point);
+    Vec2::new(mm.x, plan.canvas_size.1 - mm.y)
+}
+
+/// The fiducials of a plan with their positions in millimeters from the bottom left corner
+/// of the canvas, like `machine_point`
+pub(crate) fn machine_fiducials(plan: &ScribePlan) -> Vec<Fiducial> {
+    plan.fiducials
+        .iter()
+        .map(|fiducial| Fiducial {
+            position: machine_point(plan, plan.from_mm(fiducial.position)),
+            ..fiducial.clone()
+        })
+        .collect()
+}
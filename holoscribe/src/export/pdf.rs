@@ -0,0 +1,604 @@
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::f32::consts::FRAC_PI_2;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use super::{machine_fiducials, machine_point, Exporter, Provenance};
+use crate::scriber::{Fiducial, ScribePlan, Shape};
+
+type Num = f32;
+
+/// PDF points per millimeter
+const POINTS_PER_MM: Num = 72.0 / 25.4;
+/// Height of a line of title block text in mm
+const LINE_HEIGHT: Num = 4.0;
+/// Half the size of an alignment cross in mm
+const MARK_SIZE: Num = 4.0;
+
+/// Height in mm of a title block with the page and scale and `parameters` lines
+fn title_height(parameters: usize) -> Num {
+    LINE_HEIGHT * (parameters + 2) as Num + 2.0
+}
+
+/// Paper and layout settings for `PdfExporter`. Lengths are in mm.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PdfConfig {
+    /// Width and height of the paper, A4 portrait by default
+    pub paper_size: (Num, Num),
+    /// Blank border the printer may not reach
+    pub page_margin: Num,
+    /// How much neighboring pages share, for gluing them together
+    pub overlap: Num,
+    pub line_width: Num,
+    /// Draw alignment crosses in the overlaps and the outline of each page's tile
+    pub alignment_marks: bool,
+}
+
+impl PdfConfig {
+    /// Check that the paper has room for some of the canvas inside the margins and a title
+    /// block without parameters, and that the overlap is at least 0 and smaller than that
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        self.validate_tiles(title_height(0))
+    }
+
+    /// Size of the canvas area printed on each page, below a title block `title_height` high
+    fn tile_size(&self, title_height: Num) -> Vec2 {
+        let (width, height) = self.paper_size;
+        let margin = self.page_margin;
+        Vec2::new(width - 2.0 * margin, height - 2.0 * margin - title_height)
+    }
+
+    /// Check that the tiles below a title block `title_height` high have a size, and that
+    /// each page covers some of the canvas the ones before it don't
+    fn validate_tiles(&self, title_height: Num) -> Result<(), Box<dyn Error>> {
+        let size = self.tile_size(title_height);
+        if size.x.is_nan() || size.y.is_nan() || size.min_element() <= 0.0 {
+            let (width, height) = self.paper_size;
+            return Err(format!(
+                "{width} x {height} mm paper leaves no room for the drawing inside the {} mm \
+                 margins and the {title_height} mm title block",
+                self.page_margin
+            )
+            .into());
+        }
+        if !(0.0..size.min_element()).contains(&self.overlap) {
+            return Err(format!(
+                "overlap of {} mm must be at least 0 and smaller than the {} x {} mm printed \
+                 on each page",
+                self.overlap, size.x, size.y
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+impl Default for PdfConfig {
+    fn default() -> Self {
+        Self {
+            paper_size: (210.0, 297.0),
+            page_margin: 10.0,
+            overlap: 15.0,
+            line_width: 0.2,
+            alignment_marks: true,
+        }
+    }
+}
+
+/// Writes a plan as a PDF at true scale, for printed templates. Plans larger than the paper
+/// are tiled across pages, row by row from the top left corner of the canvas.
+pub struct PdfExporter {
+    config: PdfConfig,
+    parameters: Vec<String>,
+}
+
+/// Where the part of the canvas printed on one page lies, in mm from its bottom left corner
+struct Tile {
+    row: usize,
+    column: usize,
+    origin: Vec2,
+}
+
+impl PdfExporter {
+    pub fn new(config: PdfConfig) -> Self {
+        Self {
+            config,
+            parameters: Vec::new(),
+        }
+    }
+
+    /// Lines describing how the plan was generated, printed in the title block of every page
+    pub fn parameters(mut self, parameters: Vec<String>) -> Self {
+        self.parameters = parameters;
+        self
+    }
+
+    /// Check the config as `PdfConfig::validate` does, with the title block these parameters
+    /// need
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        self.config.validate_tiles(self.title_height())
+    }
+
+    fn title_height(&self) -> Num {
+        title_height(self.parameters.len())
+    }
+
+    /// Size of the canvas area printed on each page
+    fn tile_size(&self) -> Vec2 {
+        self.config.tile_size(self.title_height())
+    }
+
+    fn tiles(&self, plan: &ScribePlan) -> Vec<Tile> {
+        let size = self.tile_size();
+        let canvas = Vec2::new(plan.canvas_size.0, plan.canvas_size.1);
+        let step = size - self.config.overlap;
+        let count = |canvas: Num, size: Num, step: Num| {
+            ((canvas - size).max(0.0) / step).ceil() as usize + 1
+        };
+        let (columns, rows) = (
+            count(canvas.x, size.x, step.x),
+            count(canvas.y, size.y, step.y),
+        );
+        let mut tiles = Vec::new();
+        for row in 0..rows {
+            for column in 0..columns {
+                let x = column as Num * step.x;
+                // Rows go down from the top of the canvas, y points up
+                let y = canvas.y - row as Num * step.y - size.y;
+                tiles.push(Tile {
+                    row,
+                    column,
+                    origin: Vec2::new(x, y),
+                });
+            }
+        }
+        tiles
+    }
+
+    /// The path of a shape in canvas mm, with y up
+    fn path(&self, plan: &ScribePlan, shape: &Shape) -> String {
+        let at = |point: Vec2| machine_point(plan, point);
+        let mut path = String::new();
+        let curves = match shape {
+            Shape::Circle { center, radius } => {
+                arc_curves(*center, Vec2::splat(*radius), 0.0, 4.0 * FRAC_PI_2)
+            }
+            Shape::Arc {
+                center,
+                radius,
+                start,
+                sweep,
+            } => arc_curves(*center, Vec2::splat(*radius), *start, *sweep),
+            Shape::EllipticalArc {
+                center,
+                radii,
+                start,
+                sweep,
+            } => arc_curves(*center, *radii, *start, *sweep),
+            Shape::Quadratic {
+                start,
+                control,
+                end,
+            } => vec![[
+                *start,
+                *start + 2.0 / 3.0 * (*control - *start),
+                *end + 2.0 / 3.0 * (*control - *end),
+                *end,
+            ]],
+            Shape::Polyline { points } => {
+                for (index, &point) in points.iter().enumerate() {
+                    let point = at(point);
+                    let operator = if index == 0 { "m" } else { "l" };
+                    writeln!(path, "{} {} {operator}", num(point.x), num(point.y)).unwrap();
+                }
+                return path;
+            }
+        };
+        if let Some(first) = curves.first() {
+            let start = at(first[0]);
+            writeln!(path, "{} {} m", num(start.x), num(start.y)).unwrap();
+        }
+        for curve in curves {
+            let [_, a, b, c] = curve.map(at);
+            writeln!(
+                path,
+                "{} {} {} {} {} {} c",
+                num(a.x),
+                num(a.y),
+                num(b.x),
+                num(b.y),
+                num(c.x),
+                num(c.y)
+            )
+            .unwrap();
+        }
+        path
+    }
+
+    /// Crosses at every corner of the overlaps and the outline of the tile
+    fn alignment_marks(&self, plan: &ScribePlan, tile: &Tile) -> String {
+        let size = self.tile_size();
+        let step = size - self.config.overlap;
+        let canvas = Vec2::new(plan.canvas_size.0, plan.canvas_size.1);
+        let mut marks = String::new();
+        writeln!(
+            marks,
+            "[2 2] 0 d {} {} {} {} re S [] 0 d",
+            num(tile.origin.x),
+            num(tile.origin.y),
+            num(size.x),
+            num(size.y)
+        )
+        .unwrap();
+        // The middle of each overlap, measured from the top left corner like the tiles
+        let half = self.config.overlap / 2.0;
+        let xs = (1..).map(|i| i as Num * step.x + half);
+        let ys = (1..).map(|i| canvas.y - i as Num * step.y - half);
+        for x in xs.take_while(|&x| x < canvas.x) {
+            for y in ys.clone().take_while(|&y| y > 0.0) {
+                let inside = |value: Num, origin: Num, size: Num| {
+                    value >= origin - MARK_SIZE && value <= origin + size + MARK_SIZE
+                };
+                if inside(x, tile.origin.x, size.x) && inside(y, tile.origin.y, size.y) {
+                    writeln!(
+                        marks,
+                        "{} {} m {} {} l {} {} m {} {} l S",
+                        num(x - MARK_SIZE),
+                        num(y),
+                        num(x + MARK_SIZE),
+                        num(y),
+                        num(x),
+                        num(y - MARK_SIZE),
+                        num(x),
+                        num(y + MARK_SIZE)
+                    )
+                    .unwrap();
+                }
+            }
+        }
+        marks
+    }
+
+    fn title_block(&self, plan: &ScribePlan, tile: &Tile, page: usize, pages: usize) -> String {
+        let margin = self.config.page_margin;
+        let width = self.tile_size().x;
+        let height = self.title_height() - 2.0;
+        let mut lines = vec![
+            format!(
+                "Page {page} of {pages}: row {}, column {}",
+                tile.row + 1,
+                tile.column + 1
+            ),
+            format!(
+                "Canvas {} x {} mm, {} mm per model unit. Print at 100% scale",
+                plan.canvas_size.0,
+                plan.canvas_size.1,
+                num(plan.mm_per_unit())
+            ),
+        ];
+        lines.extend(self.parameters.iter().cloned());
+
+        let mut block = String::new();
+        writeln!(
+            block,
+            "{} {} {} {} re S",
+            num(margin),
+            num(margin),
+            num(width),
+            num(height)
+        )
+        .unwrap();
+        writeln!(block, "BT /F1 3 Tf").unwrap();
+        for (index, line) in lines.iter().enumerate() {
+            let y = margin + height - LINE_HEIGHT * (index + 1) as Num + 1.0;
+            writeln!(
+                block,
+                "1 0 0 1 {} {} Tm ({}) Tj",
+                num(margin + 2.0),
+                num(y),
+                escape(line)
+            )
+            .unwrap();
+        }
+        writeln!(block, "ET").unwrap();
+        block
+    }
+
+    /// The content stream of a page, in mm
+    fn page(&self, plan: &ScribePlan, tile: &Tile, page: usize, pages: usize) -> String {
+        let margin = self.config.page_margin;
+        let size = self.tile_size();
+        let mut content = String::new();
+        writeln!(
+            content,
+            "{k} 0 0 {k} 0 0 cm {} w",
+            num(self.config.line_width),
+            k = POINTS_PER_MM
+        )
+        .unwrap();
+        content.push_str(&self.title_block(plan, tile, page, pages));
+
+        // Move the tile into the printable area and clip everything outside of it
+        let offset = Vec2::new(margin, margin + self.title_height()) - tile.origin;
+        writeln!(
+            content,
+            "q 1 0 0 1 {} {} cm {} {} {} {} re W n",
+            num(offset.x),
+            num(offset.y),
+            num(tile.origin.x),
+            num(tile.origin.y),
+            num(size.x),
+            num(size.y)
+        )
+        .unwrap();
+        for primitive in plan.in_order() {
+            content.push_str(&self.path(plan, &primitive.shape));
+            content.push_str("S\n");
+        }
+        if self.config.alignment_marks {
+            content.push_str(&self.alignment_marks(plan, tile));
+        }
+        content.push_str("Q\n");
+        content
+    }
+}
+
+impl Exporter for PdfExporter {
+    fn export(&self, plan: &ScribePlan, out: &mut dyn Write) -> io::Result<()> {
+        self.validate()
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error.to_string()))?;
+        let tiles = self.tiles(plan);
+        let (width, height) = self.config.paper_size;
+
+        // Catalog, page tree and font come first, then a page and its content per tile
+        let page_ids: Vec<usize> = (0..tiles.len()).map(|i| 4 + 2 * i).collect();
+        let kids: Vec<String> = page_ids.iter().map(|id| format!("{id} 0 R")).collect();
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                kids.join(" "),
+                tiles.len()
+            ),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+                .to_string(),
+        ];
+        for (index, tile) in tiles.iter().enumerate() {
+            objects.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+                 /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+                num(width * POINTS_PER_MM),
+                num(height * POINTS_PER_MM),
+                page_ids[index] + 1
+            ));
+            let content = self.page(plan, tile, index + 1, tiles.len());
+            objects.push(format!(
+                "<< /Length {} >>\nstream\n{content}endstream",
+                content.len()
+            ));
+        }
+        let info = info(plan).map(|info| {
+            objects.push(info);
+            objects.len()
+        });
+
+        // The whole provenance and the fiducials go in comments, the document information only
+        // has a summary of the provenance
+        let mut pdf = String::from("%PDF-1.4\n");
+        for line in plan.provenance.iter().flat_map(Provenance::lines) {
+            writeln!(pdf, "% {line}").unwrap();
+        }
+        for fiducial in machine_fiducials(plan) {
+            writeln!(pdf, "% fiducial {fiducial}").unwrap();
+        }
+        let mut offsets = Vec::new();
+        for (index, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            write!(pdf, "{} 0 obj\n{object}\nendobj\n", index + 1).unwrap();
+        }
+        let xref = pdf.len();
+        write!(pdf, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).unwrap();
+        for offset in offsets {
+            writeln!(pdf, "{offset:010} 00000 n ").unwrap();
+        }
+        let info = info
+            .map(|id| format!(" /Info {id} 0 R"))
+            .unwrap_or_default();
+        write!(
+            pdf,
+            "trailer\n<< /Size {} /Root 1 0 R{info} >>\nstartxref\n{xref}\n%%EOF\n",
+            objects.len() + 1
+        )
+        .unwrap();
+        out.write_all(pdf.as_bytes())
+    }
+}
+
+/// The document information dictionary of a plan, with its provenance and fiducials, `None`
+/// if it has neither
+fn info(plan: &ScribePlan) -> Option<String> {
+    let mut entries = Vec::new();
+    if let Some(provenance) = &plan.provenance {
+        // D:YYYYMMDDHHmmSS from an RFC 3339 timestamp
+        let date: String = provenance
+            .timestamp
+            .chars()
+            .filter(char::is_ascii_digit)
+            .take(14)
+            .collect();
+        entries.push(format!(
+            "/Title ({}) /Subject ({}) /Keywords (sha256 {}) /Creator ({}) /Producer (holoscribe) \
+             /CreationDate (D:{date}Z)",
+            escape(&provenance.model),
+            escape(&provenance.arguments.join(" ")),
+            escape(&provenance.model_hash),
+            escape(&provenance.tool)
+        ));
+    }
+    let fiducials: Vec<String> = machine_fiducials(plan)
+        .iter()
+        .map(Fiducial::to_string)
+        .collect();
+    if !fiducials.is_empty() {
+        entries.push(format!("/Fiducials ({})", escape(&fiducials.join("; "))));
+    }
+    (!entries.is_empty()).then(|| format!("<< {} >>", entries.join(" ")))
+}
+
+/// Cubic Bézier curves of at most a quarter turn each along an elliptical arc
+fn arc_curves(center: Vec2, radii: Vec2, start: Num, sweep: Num) -> Vec<[Vec2; 4]> {
+    let steps = ((sweep.abs() / FRAC_PI_2).ceil() as usize).max(1);
+    let step = sweep / steps as Num;
+    // Length of the tangents that best match a circular arc of `step`
+    let k = 4.0 / 3.0 * (step / 4.0).tan();
+    let at = |t: Num| center + radii * Vec2::new(t.cos(), t.sin());
+    let tangent = |t: Num| radii * Vec2::new(-t.sin(), t.cos());
+    (0..steps)
+        .map(|i| {
+            let (t0, t1) = (start + step * i as Num, start + step * (i + 1) as Num);
+            [
+                at(t0),
+                at(t0) + k * tangent(t0),
+                at(t1) - k * tangent(t1),
+                at(t1),
+            ]
+        })
+        .collect()
+}
+
+/// Escape text for a PDF string literal in WinAnsiEncoding, the encoding of the font.
+/// Characters it has beyond ASCII, which are Latin-1 and a few typographic ones, are written
+/// as octal escapes, and the ones it doesn't have are replaced
+fn escape(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '(' | ')' | '\\' => format!("\\{c}"),
+            c if c.is_ascii() && !c.is_ascii_control() => c.to_string(),
+            c => match win_ansi(c) {
+                Some(code) => format!("\\{code:03o}"),
+                None => "?".to_string(),
+            },
+        })
+        .collect()
+}
+
+/// The WinAnsiEncoding code of a character outside ASCII, if it has one
+fn win_ansi(c: char) -> Option<u8> {
+    // Above 0x9f WinAnsiEncoding is Latin-1, below it has typographic characters instead of
+    // control codes
+    const EXTRAS: [(char, u8); 13] = [
+        ('€', 0x80),
+        ('‚', 0x82),
+        ('„', 0x84),
+        ('…', 0x85),
+        ('‘', 0x91),
+        ('’', 0x92),
+        ('“', 0x93),
+        ('”', 0x94),
+        ('•', 0x95),
+        ('–', 0x96),
+        ('—', 0x97),
+        ('™', 0x99),
+        ('‰', 0x89),
+    ];
+    match u32::from(c) {
+        code @ 0xa0..=0xff => Some(code as u8),
+        _ => EXTRAS
+            .iter()
+            .find(|(extra, _)| *extra == c)
+            .map(|(_, code)| *code),
+    }
+}
+
+fn num(value: Num) -> String {
+    // Adding zero turns -0 into 0
+    format!("{:.3}", value + 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scriber::{FiducialKind, ImageKind, Rect};
+
+    #[test]
+    fn test_pdf_tiles() {
+        // A 400x300mm canvas takes 3 by 2 portrait A4 pages
+        let mut plan = ScribePlan::new(Rect::new(0.0, 0.0, 400.0, 300.0), (400.0, 300.0));
+        let circle = Shape::Circle {
+            center: Vec2::new(200.0, 150.0),
+            radius: 50.0,
+        };
+        plan.push(circle, 1.0, ImageKind::Virtual, "circles");
+
+        let exporter = PdfExporter::new(PdfConfig::default())
+            .parameters(vec!["strategy: circle (default)".to_string()]);
+        let mut out = Vec::new();
+        exporter.export(&plan, &mut out).unwrap();
+        let pdf = String::from_utf8(out).unwrap();
+        assert!(pdf.starts_with("%PDF-1.4\n"));
+        assert!(pdf.contains("/Count 6"));
+        assert!(pdf.contains("(Page 6 of 6: row 2, column 3) Tj"));
+        assert!(pdf.contains("(strategy: circle \\(default\\)) Tj"));
+        assert!(pdf.contains("/Encoding /WinAnsiEncoding"));
+        assert!(!pdf.contains("/Info"));
+
+        // Every object is where the cross-reference table says
+        let xref = pdf.find("xref\n").unwrap();
+        let offsets = pdf[xref..].lines().skip(3).take(15);
+        for (index, line) in offsets.enumerate() {
+            let offset: usize = line[..10].parse().unwrap();
+            assert!(pdf[offset..].starts_with(&format!("{} 0 obj", index + 1)));
+        }
+
+        // Fiducials go in the comments and the document information, in mm from the bottom left
+        plan.fiducials.push(Fiducial {
+            kind: FiducialKind::ScaleBar,
+            position: Vec2::new(2.0, 298.0),
+            size: 10.0,
+        });
+        let mut out = Vec::new();
+        exporter.export(&plan, &mut out).unwrap();
+        let pdf = String::from_utf8(out).unwrap();
+        assert!(pdf.contains("\n% fiducial scale_bar at 2.00, 2.00 mm, 10.00 mm\n"));
+        assert!(pdf.contains("/Fiducials (scale_bar at 2.00, 2.00 mm, 10.00 mm) >>"));
+        assert!(pdf.contains("/Info 16 0 R"));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a (b) \\"), "a \\(b\\) \\\\");
+        // Latin-1 and the typographic extras of WinAnsiEncoding are kept, the rest replaced
+        assert_eq!(escape("20°C – ø"), "20\\260C \\226 \\370");
+        assert_eq!(escape("ж\n"), "??");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(PdfConfig::default().validate().is_ok());
+        let config = |overlap, page_margin| PdfConfig {
+            overlap,
+            page_margin,
+            ..Default::default()
+        };
+        // A4 portrait is 190 mm wide inside the default margins
+        assert!(config(189.0, 10.0).validate().is_ok());
+        assert!(config(190.0, 10.0).validate().is_err());
+        assert!(config(-1.0, 10.0).validate().is_err());
+        assert!(config(0.0, 110.0).validate().is_err());
+
+        // The title block needs more room with every parameter
+        let config = PdfConfig {
+            paper_size: (210.0, 40.0),
+            overlap: 0.0,
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        let exporter = PdfExporter::new(config).parameters(vec!["model".to_string(); 3]);
+        assert!(exporter.validate().is_err());
+        let plan = ScribePlan::new(Rect::new(0.0, 0.0, 10.0, 10.0), (10.0, 10.0));
+        assert!(exporter.export(&plan, &mut Vec::new()).is_err());
+    }
+}
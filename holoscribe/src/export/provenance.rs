@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+
+/// What exporters put in front of every line of a provenance, to find it again among the
+/// other comments of a file
+pub const PROVENANCE_PREFIX: &str = "provenance ";
+
+/// Where an output came from: the source model, everything it was made with and when.
+/// Exporters write it into the output, so it can be traced back and made again
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// Path of the source model, as it was given
+    pub model: String,
+    /// SHA-256 of the source model, in hex
+    pub model_hash: String,
+    /// Other files the options refer to, like calibration tables and plate outlines, as
+    /// their path and SHA-256 in hex
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<(String, String)>,
+    /// The command line options the output was made with, leaving out where it was written
+    pub arguments: Vec<String>,
+    /// Where the output was written
+    pub output: String,
+    /// Contents of the config file, if one was used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<String>,
+    /// Name and version of the tool that made the output
+    pub tool: String,
+    /// When the output was made, in RFC 3339
+    pub timestamp: String,
+}
+
+impl Provenance {
+    /// One `key: value` line per field, a `file` line with the hash and path of each other
+    /// file, an `argument` line per argument and a `config` line per line of the config file,
+    /// each starting with `PROVENANCE_PREFIX`
+    pub fn lines(&self) -> Vec<String> {
+        let files: Vec<String> = self
+            .files
+            .iter()
+            .map(|(path, hash)| format!("{hash} {path}"))
+            .collect();
+        let mut fields = vec![("model", self.model.as_str())];
+        fields.push(("model_hash", &self.model_hash));
+        fields.extend(files.iter().map(|file| ("file", file.as_str())));
+        fields.extend(self.arguments.iter().map(|arg| ("argument", arg.as_str())));
+        fields.push(("output", &self.output));
+        if let Some(config) = &self.config {
+            fields.extend(config.lines().map(|line| ("config", line)));
+        }
+        fields.push(("tool", &self.tool));
+        fields.push(("timestamp", &self.timestamp));
+        fields
+            .into_iter()
+            .map(|(key, value)| format!("{PROVENANCE_PREFIX}{key}: {value}"))
+            .collect()
+    }
+
+    /// Read back a provenance written by `lines`, from text that has its lines somewhere in
+    /// it, after any comment characters. `None` if there is none
+    pub fn from_text(text: &str) -> Option<Self> {
+        let mut provenance = Provenance::default();
+        let mut config: Option<Vec<&str>> = None;
+        let mut found = false;
+        for line in text.lines() {
+            let Some((_, field)) = line.split_once(PROVENANCE_PREFIX) else {
+                continue;
+            };
+            let Some((key, value)) = field.split_once(": ").or(field.split_once(':')) else {
+                continue;
+            };
+            found = true;
+            match key {
+                "model" => provenance.model = value.to_string(),
+                "model_hash" => provenance.model_hash = value.to_string(),
+                "file" => {
+                    if let Some((hash, path)) = value.split_once(' ') {
+                        provenance.files.push((path.to_string(), hash.to_string()));
+                    }
+                }
+                "argument" => provenance.arguments.push(value.to_string()),
+                "output" => provenance.output = value.to_string(),
+                "config" => config.get_or_insert_with(Vec::new).push(value),
+                "tool" => provenance.tool = value.to_string(),
+                "timestamp" => provenance.timestamp = value.to_string(),
+                _ => {}
+            }
+        }
+        provenance.config = config.map(|lines| lines.join("\n"));
+        found.then_some(provenance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provenance_lines() {
+        let provenance = Provenance {
+            model: "samples/lamp.obj".to_string(),
+            model_hash: "ab12".to_string(),
+            files: vec![("calibration table.csv".to_string(), "cd34".to_string())],
+            arguments: ["-i", "samples/lamp.obj", "-c", "100x100mm", "--stroke", ""]
+                .map(String::from)
+                .to_vec(),
+            output: "lamp.gcode".to_string(),
+            config: Some("margin = 5\n\n[gcode]\nsafe_z = 2".to_string()),
+            tool: "holocli 0.1.0".to_string(),
+            timestamp: "2024-05-01T12:00:00Z".to_string(),
+        };
+        let commented: Vec<String> = provenance
+            .lines()
+            .iter()
+            .map(|line| format!("; {line}"))
+            .collect();
+        let text = format!("G21\n{}\nG0 Z5\n", commented.join("\n"));
+        assert_eq!(Provenance::from_text(&text), Some(provenance));
+        assert_eq!(Provenance::from_text("G21\nG0 Z5\n"), None);
+    }
+}
@@ -0,0 +1,436 @@
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::io::{self, Write};
+use svg::node::element::path::Data;
+use svg::node::element::{Circle, Element, Group, Path, Rectangle, SVG};
+use svg::{Document, Node};
+
+use super::Exporter;
+use crate::scriber::{LayerStyles, Primitive, Rect, ScribePlan, ScriberConfig, Shape};
+
+type Num = f32;
+
+const INKSCAPE_NAMESPACE: &str = "http://www.inkscape.org/namespaces/inkscape";
+
+/// Which SVG elements the shapes of a plan are written as
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SvgElements {
+    /// `<circle>` for circles and `<path>` with absolute commands for everything else
+    #[default]
+    Native,
+    /// `<path>` with absolute commands for everything, for software that mangles circles
+    Paths,
+    /// `<path>` with relative commands after the first move for everything
+    RelativeArcs,
+}
+
+/// Writes a plan as an SVG document, styled by a `ScriberConfig`
+pub struct SvgExporter {
+    config: ScriberConfig,
+    elements: SvgElements,
+}
+
+impl SvgExporter {
+    pub fn new(config: ScriberConfig) -> Self {
+        Self {
+            config,
+            elements: SvgElements::default(),
+        }
+    }
+
+    /// Set which elements the shapes are written as
+    pub fn elements(mut self, elements: SvgElements) -> Self {
+        self.elements = elements;
+        self
+    }
+
+    pub fn document(&self, plan: &ScribePlan) -> Document {
+        // Named groups become Inkscape layers at the top level, each with its own copy of the
+        // viewbox. Ungrouped primitives go below them, on the background
+        let styles = LayerStyles::new(&self.config, 1.0 / plan.mm_per_unit());
+        let mut viewbox = self.view_box(plan);
+        let mut layers = Vec::new();
+        for (name, primitives) in plan.groups() {
+            let Some(name) = name else {
+                for primitive in primitives {
+                    viewbox.append(element(primitive, &styles, self.elements));
+                }
+                continue;
+            };
+            let mut layer_box = SVG::new().set("viewBox", plan.view_box.as_tuple());
+            for primitive in primitives {
+                layer_box.append(element(primitive, &styles, self.elements));
+            }
+            layers.push(layer(layers.len() + 1, name).add(layer_box));
+        }
+
+        let mut doc = self.root(plan).add(viewbox);
+        for layer in layers {
+            doc = doc.add(layer);
+        }
+        doc
+    }
+
+    /// Start writing the SVG of a plan to `out`, to write its primitives one by one as they
+    /// are made instead of building the document in memory. Only the viewbox, canvas,
+    /// fiducials and provenance of `plan` are used, not its primitives
+    pub fn stream<W: Write>(&self, plan: &ScribePlan, mut out: W) -> io::Result<SvgStream<'_, W>> {
+        let root = self.root(plan);
+        write!(
+            out,
+            "{}\n{}",
+            start(&root, "svg"),
+            start(&self.view_box(plan), "svg")
+        )?;
+        Ok(SvgStream {
+            exporter: self,
+            out,
+            view_box: plan.view_box,
+            units_per_mm: 1.0 / plan.mm_per_unit(),
+            group: None,
+            layers: 0,
+        })
+    }
+
+    /// The containing document, which is the canvas in mm, with the metadata of the plan
+    fn root(&self, plan: &ScribePlan) -> Document {
+        let (width, height) = plan.canvas_size;
+        let mut doc = Document::new()
+            .set("width", format!("{width}mm"))
+            .set("height", format!("{height}mm"))
+            .set("viewBox", (0.0, 0.0, width, height));
+
+        let mut lines = Vec::new();
+        if let Some(provenance) = &plan.provenance {
+            lines.extend(provenance.lines());
+        }
+        if !plan.fiducials.is_empty() {
+            // Positions are in document units, which are mm from the top left of the canvas
+            lines.push("fiducials:".to_string());
+            lines.extend(plan.fiducials.iter().map(|f| f.to_string()));
+        }
+        if !lines.is_empty() {
+            let mut metadata = Element::new("metadata");
+            metadata.append(svg::node::Text::new(format!("\n{}\n", lines.join("\n"))));
+            doc = doc.add(metadata);
+        }
+        doc
+    }
+
+    /// The viewbox of the ungrouped primitives, with the background
+    fn view_box(&self, plan: &ScribePlan) -> SVG {
+        let viewbox = SVG::new().set("viewBox", plan.view_box.as_tuple());
+        match &self.config.background {
+            Some(background) => viewbox.add(
+                Rectangle::new()
+                    .set("x", plan.view_box.x)
+                    .set("y", plan.view_box.y)
+                    .set("width", plan.view_box.width)
+                    .set("height", plan.view_box.height)
+                    .set("fill", background.as_str()),
+            ),
+            None => viewbox,
+        }
+    }
+}
+
+/// Writes an SVG primitive by primitive as they are made, laid out like
+/// `SvgExporter::document`, so that memory use doesn't grow with the number of primitives.
+/// Made by `SvgExporter::stream`
+pub struct SvgStream<'a, W: Write> {
+    exporter: &'a SvgExporter,
+    out: W,
+    view_box: Rect,
+    units_per_mm: Num,
+    /// Group of the layer being written into, `None` for a viewbox of ungrouped primitives
+    group: Option<String>,
+    layers: usize,
+}
+
+impl<W: Write> SvgStream<'_, W> {
+    /// Write a primitive. A primitive of another group than the one before starts a new
+    /// layer, or a new viewbox if it is ungrouped, so they should come group by group as
+    /// `ScribePlan::groups` gives them
+    pub fn write(&mut self, primitive: &Primitive) -> io::Result<()> {
+        if primitive.group != self.group {
+            self.close()?;
+            if let Some(name) = &primitive.group {
+                self.layers += 1;
+                write!(self.out, "\n{}", start(&layer(self.layers, name), "g"))?;
+            }
+            let view_box = SVG::new().set("viewBox", self.view_box.as_tuple());
+            write!(self.out, "\n{}", start(&view_box, "svg"))?;
+            self.group = primitive.group.clone();
+        }
+        let styles = LayerStyles::new(&self.exporter.config, self.units_per_mm);
+        let element = element(primitive, &styles, self.exporter.elements);
+        write!(self.out, "\n{element}")
+    }
+
+    /// End the document and hand back the sink
+    pub fn finish(mut self) -> io::Result<W> {
+        self.close()?;
+        write!(self.out, "\n</svg>")?;
+        Ok(self.out)
+    }
+
+    /// End the viewbox, and layer, being written into
+    fn close(&mut self) -> io::Result<()> {
+        write!(self.out, "\n</svg>")?;
+        if self.group.is_some() {
+            write!(self.out, "\n</g>")?;
+        }
+        Ok(())
+    }
+}
+
+/// An Inkscape layer with its number and name. It declares the Inkscape namespace itself,
+/// as whether there are layers is only known at the end when streaming
+fn layer(number: usize, name: &str) -> Group {
+    Group::new()
+        .set("xmlns:inkscape", INKSCAPE_NAMESPACE)
+        .set("id", format!("layer-{number}"))
+        .set("inkscape:groupmode", "layer")
+        .set("inkscape:label", name)
+}
+
+/// An element as written by the svg crate, without its end tag, to write more children
+/// after the ones it has
+fn start(node: &dyn Node, name: &str) -> String {
+    let text = node.to_string();
+    match text.strip_suffix("/>") {
+        Some(tag) => format!("{tag}>"),
+        None => text
+            .strip_suffix(&format!("\n</{name}>"))
+            .unwrap_or(&text)
+            .to_string(),
+    }
+}
+
+impl Exporter for SvgExporter {
+    fn export(&self, plan: &ScribePlan, out: &mut dyn Write) -> io::Result<()> {
+        svg::write(out, &self.document(plan))
+    }
+}
+
+/// The styled SVG element drawing a primitive
+fn element(primitive: &Primitive, styles: &LayerStyles, elements: SvgElements) -> Box<dyn Node> {
+    let image = primitive.image.as_str();
+    let layer = primitive.layer.as_str();
+    match &primitive.shape {
+        Shape::Circle { center, radius } if elements == SvgElements::Native => {
+            let circle = Circle::new()
+                .set("cx", center.x)
+                .set("cy", center.y)
+                .set("r", *radius)
+                .set("data-image", image);
+            Box::new(styles.apply(layer, circle))
+        }
+        shape => {
+            let relative = elements == SvgElements::RelativeArcs;
+            let path = Path::new()
+                .set("d", path_data(shape, relative))
+                .set("data-image", image);
+            Box::new(styles.apply(layer, path))
+        }
+    }
+}
+
+/// A drawing command of path data, ending at an absolute position
+enum Segment {
+    Line(Vec2),
+    Quadratic {
+        control: Vec2,
+        to: Vec2,
+    },
+    Arc {
+        radii: Vec2,
+        sweep_flag: u8,
+        to: Vec2,
+    },
+}
+
+impl Segment {
+    fn to(&self) -> Vec2 {
+        match *self {
+            Segment::Line(to) | Segment::Quadratic { to, .. } | Segment::Arc { to, .. } => to,
+        }
+    }
+}
+
+/// Path data of a shape. With `relative`, every command after the first move is relative to
+/// the end of the previous one
+fn path_data(shape: &Shape, relative: bool) -> Data {
+    let (start, segments) = segments(shape);
+    let mut data = Data::new().move_to((start.x, start.y));
+    let mut from = start;
+    for segment in segments {
+        let origin = if relative { from } else { Vec2::ZERO };
+        let to = segment.to() - origin;
+        data = match segment {
+            Segment::Line(_) if relative => data.line_by((to.x, to.y)),
+            Segment::Line(_) => data.line_to((to.x, to.y)),
+            Segment::Quadratic { control, .. } => {
+                let control = control - origin;
+                let parameters = (control.x, control.y, to.x, to.y);
+                if relative {
+                    data.quadratic_curve_by(parameters)
+                } else {
+                    data.quadratic_curve_to(parameters)
+                }
+            }
+            Segment::Arc {
+                radii, sweep_flag, ..
+            } => {
+                let parameters = (radii.x, radii.y, 0, 0, sweep_flag, to.x, to.y);
+                if relative {
+                    data.elliptical_arc_by(parameters)
+                } else {
+                    data.elliptical_arc_to(parameters)
+                }
+            }
+        };
+        from = segment.to();
+    }
+    data
+}
+
+/// Where a shape starts and the segments drawing it from there
+fn segments(shape: &Shape) -> (Vec2, Vec<Segment>) {
+    match shape {
+        Shape::Circle { center, radius } => {
+            arc_segments(*center, Vec2::splat(*radius), 0.0, 2.0 * PI)
+        }
+        Shape::Arc {
+            center,
+            radius,
+            start,
+            sweep,
+        } => arc_segments(*center, Vec2::splat(*radius), *start, *sweep),
+        Shape::EllipticalArc {
+            center,
+            radii,
+            start,
+            sweep,
+        } => arc_segments(*center, *radii, *start, *sweep),
+        Shape::Quadratic {
+            start,
+            control,
+            end,
+        } => (
+            *start,
+            vec![Segment::Quadratic {
+                control: *control,
+                to: *end,
+            }],
+        ),
+        Shape::Polyline { points } => (
+            points.first().copied().unwrap_or_default(),
+            points
+                .iter()
+                .skip(1)
+                .map(|point| Segment::Line(*point))
+                .collect(),
+        ),
+    }
+}
+
+/// Segments of an elliptical arc. Arcs of a full turn or more are split in two, as SVG
+/// can't draw an arc ending where it starts.
+fn arc_segments(center: Vec2, radii: Vec2, start: f32, sweep: f32) -> (Vec2, Vec<Segment>) {
+    let at = |t: f32| center + radii * Vec2::new(t.cos(), t.sin());
+    let sweep = sweep.clamp(-2.0 * PI, 2.0 * PI);
+    let steps = if sweep.abs() > PI { 2 } else { 1 };
+    let sweep_flag = if sweep > 0.0 { 1 } else { 0 };
+    let segments = (1..=steps)
+        .map(|step| Segment::Arc {
+            radii,
+            sweep_flag,
+            to: at(start + sweep * step as f32 / steps as f32),
+        })
+        .collect();
+    (at(start), segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scriber::{Grouping, ImageKind, Rect};
+
+    #[test]
+    fn test_stream_matches_document() {
+        let config = ScriberConfig {
+            background: Some("white".to_string()),
+            ..Default::default()
+        };
+        let exporter = SvgExporter::new(config);
+        let streamed = |plan: &ScribePlan| {
+            let mut stream = exporter.stream(plan, Vec::new()).unwrap();
+            for (_, primitives) in plan.groups() {
+                for primitive in primitives {
+                    stream.write(primitive).unwrap();
+                }
+            }
+            String::from_utf8(stream.finish().unwrap()).unwrap()
+        };
+
+        // Ungrouped primitives, as `Scriber::scribe_to` writes them, then some in groups
+        let mut plan = ScribePlan::new(Rect::new(0.0, 0.0, 10.0, 10.0), (100.0, 100.0));
+        for (index, group) in [None, None, Some("a"), Some("b")].into_iter().enumerate() {
+            let circle = Shape::Circle {
+                center: Vec2::new(5.0, 5.0),
+                radius: index as Num + 1.0,
+            };
+            plan.push(circle, 1.0, ImageKind::Virtual, "circles");
+            plan.primitives[index].group = group.map(String::from);
+            if index == 1 {
+                assert!(!streamed(&plan).contains("inkscape:groupmode"));
+                assert_eq!(streamed(&plan), exporter.document(&plan).to_string());
+            }
+        }
+        assert_eq!(streamed(&plan), exporter.document(&plan).to_string());
+    }
+
+    #[test]
+    fn test_groups_as_layers() {
+        let mut plan = ScribePlan::new(Rect::new(0.0, 0.0, 10.0, 10.0), (100.0, 100.0));
+        for depth in [0.5, 2.5, 1.5] {
+            let circle = Shape::Circle {
+                center: Vec2::new(5.0, 5.0),
+                radius: depth,
+            };
+            plan.push(circle, depth, ImageKind::Virtual, "circles");
+        }
+        Grouping::DepthBand { band: 2.0 }.apply(&mut plan);
+
+        let svg = SvgExporter::new(ScriberConfig::default())
+            .document(&plan)
+            .to_string();
+        assert!(svg.contains(INKSCAPE_NAMESPACE));
+        let layers: Vec<&str> = svg.split("inkscape:label=").skip(1).collect();
+        assert_eq!(layers.len(), 2);
+        assert!(layers[0].starts_with("\"depth-0\""));
+        // The third circle is scribed with the first, in the layer of the shallow band
+        assert_eq!(layers[0].matches("<circle").count(), 2);
+        assert_eq!(layers[1].matches("<circle").count(), 1);
+    }
+
+    #[test]
+    fn test_relative_arcs() {
+        let shape = Shape::Arc {
+            center: Vec2::new(5.0, 5.0),
+            radius: 1.0,
+            start: 0.0,
+            sweep: 2.0 * PI,
+        };
+        let data = path_data(&shape, true);
+        let commands: Vec<String> = data.iter().map(|command| format!("{command:?}")).collect();
+        assert_eq!(commands.len(), 3);
+        assert!(commands[1].contains("Relative"));
+        // Two half turns, from the right of the circle to the left and back
+        let d = Path::new().set("d", data).to_string();
+        assert!(d.contains("M6,5 a1,1,0,0,1,-2,0"), "{d}");
+    }
+}
@@ -0,0 +1,101 @@
+use glam::Vec2;
+use std::io::{self, Write};
+use svg::node::element::{Rectangle, Text};
+use svg::Document;
+
+use crate::scriber::{ScribePlan, Tile};
+
+type Num = f32;
+
+/// Where each plate of a tiled plan goes, for assembling the finished piece
+pub struct TileIndex {
+    canvas_size: (Num, Num),
+    /// Extent of the scribed lines in mm, `None` for an empty plan
+    bounds: Option<(Vec2, Vec2)>,
+    /// Name, top left corner and size in mm of every plate
+    plates: Vec<(String, Vec2, Vec2)>,
+}
+
+impl TileIndex {
+    pub fn new(plan: &ScribePlan, tiles: &[Tile]) -> Self {
+        let bounds = plan
+            .bounds()
+            .map(|bounds| (plan.to_mm(bounds.min()), plan.to_mm(bounds.max())));
+        let plates = tiles
+            .iter()
+            .map(|tile| {
+                let size = Vec2::from(tile.plan.canvas_size);
+                (tile.name(), tile.origin, size)
+            })
+            .collect();
+        Self {
+            canvas_size: plan.canvas_size,
+            bounds,
+            plates,
+        }
+    }
+
+    /// Write the overview made by `document`
+    pub fn write_svg(&self, out: &mut dyn Write) -> io::Result<()> {
+        svg::write(out, &self.document())
+    }
+
+    /// The overview, in mm: the canvas, the extent of the scribed lines, and every plate
+    /// outlined and labeled with its name
+    pub fn document(&self) -> Document {
+        let (width, height) = self.canvas_size;
+        let extent = self
+            .plates
+            .iter()
+            .map(|(_, origin, size)| *origin + *size)
+            .fold(Vec2::new(width, height), Vec2::max);
+        let rectangle = |min: Vec2, size: Vec2| {
+            Rectangle::new()
+                .set("x", min.x)
+                .set("y", min.y)
+                .set("width", size.x)
+                .set("height", size.y)
+                .set("fill", "none")
+        };
+
+        let mut doc = Document::new()
+            .set("width", format!("{}mm", extent.x))
+            .set("height", format!("{}mm", extent.y))
+            .set("viewBox", (0.0, 0.0, extent.x, extent.y))
+            .add(
+                rectangle(Vec2::ZERO, Vec2::new(width, height))
+                    .set("stroke", "black")
+                    .set("stroke-width", 0.5),
+            );
+        if let Some((min, max)) = self.bounds {
+            doc = doc.add(
+                rectangle(min, max - min)
+                    .set("stroke", "gray")
+                    .set("stroke-width", 0.3)
+                    .set("stroke-dasharray", "2 2"),
+            );
+        }
+        for (name, origin, size) in &self.plates {
+            let center = *origin + *size / 2.0;
+            let font_size = size.min_element() / 8.0;
+            let label = Text::new()
+                .set("x", center.x)
+                .set("y", center.y + font_size / 3.0)
+                .set("font-size", font_size)
+                .set("font-family", "sans-serif")
+                .set("text-anchor", "middle")
+                .set("fill", "blue")
+                .add(svg::node::Text::new(name.as_str()));
+            doc = doc
+                .add(
+                    rectangle(*origin, *size)
+                        .set("stroke", "blue")
+                        .set("stroke-width", 0.3)
+                        .set("fill", "blue")
+                        .set("fill-opacity", 0.05),
+                )
+                .add(label);
+        }
+        doc
+    }
+}
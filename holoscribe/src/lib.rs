@@ -1,4 +1,5 @@
 #![feature(test)]
+pub mod export;
 pub mod model;
 pub mod scriber;
 extern crate test;
@@ -26,4 +27,14 @@ mod tests {
         let scriber = scriber::Scriber::new(circle_strat, (100, 100));
         b.iter(|| scriber.scribe(&interpolated_points));
     }
+
+    #[bench]
+    fn benchmark_scribe_streaming(b: &mut Bencher) {
+        let model =
+            ObjInterpolator::from_file("tests/icosahedron.obj".to_string()).expect("invalid model");
+        let interpolated_points = model.interpolate_edges(100);
+        let circle_strat = scriber::CircleScriber::new();
+        let scriber = scriber::Scriber::new(circle_strat, (100, 100));
+        b.iter(|| scriber.scribe_to(&interpolated_points, std::io::sink()));
+    }
 }
@@ -1,9 +1,8 @@
 use glam::Vec3;
-use obj::Obj;
+use obj::{Obj, SimplePolygon};
 use std::cmp::{max, min};
-use std::collections::HashSet;
+use std::collections::BTreeSet;
 use std::error::Error;
-use std::f32::INFINITY;
 
 pub struct ObjInterpolator {
     model: Obj,
@@ -17,16 +16,46 @@ impl ObjInterpolator {
     // load an obj struct from a file
     pub fn from_file(file_path: String) -> Result<Self, Box<dyn Error>> {
         let user_defined_model: Obj = Obj::load(file_path)?;
+        // interpolate_edges reads the first group of the first object
+        let objects = &user_defined_model.data.objects;
+        if objects
+            .first()
+            .is_none_or(|object| object.groups.is_empty())
+        {
+            return Err("the model has no objects".into());
+        }
         Ok(Self::new(user_defined_model))
     }
 
     // given an Obj, interpolate points between connected vertices to simulate lines
     pub fn interpolate_edges(&self, points_per_unit: usize) -> Vec<Vec3> {
-        // extract vertices
-        //let verts = obj.data.position.clone();
+        self.interpolate_polys(&self.model.data.objects[0].groups[0].polys, points_per_unit)
+    }
+
+    // interpolate the edges of every object and group of the model separately, named
+    // object/group, leaving out the default names
+    pub fn interpolate_groups(&self, points_per_unit: usize) -> Vec<(String, Vec<Vec3>)> {
+        let mut groups = Vec::new();
+        for object in &self.model.data.objects {
+            for group in &object.groups {
+                let name = match (object.name.as_str(), group.name.as_str()) {
+                    (object, "default") => object.to_string(),
+                    ("default", group) => group.to_string(),
+                    (object, group) => format!("{object}/{group}"),
+                };
+                let points = self.interpolate_polys(&group.polys, points_per_unit);
+                if !points.is_empty() {
+                    groups.push((name, points));
+                }
+            }
+        }
+        groups
+    }
+
+    fn interpolate_polys(&self, polys: &[SimplePolygon], points_per_unit: usize) -> Vec<Vec3> {
         // extract paths
         let mut poly_paths: Vec<Vec<usize>> = Vec::new();
-        for simple_poly in self.model.data.objects[0].groups[0].polys.clone() {
+        for simple_poly in polys.iter().cloned() {
             let mut poly_path: Vec<usize> = Vec::new();
             for vert in simple_poly.0 {
                 poly_path.push(vert.0)
@@ -34,8 +63,9 @@ impl ObjInterpolator {
             poly_paths.push(poly_path)
         }
 
-        let mut edge_set = HashSet::new();
-        // sort vertex indices before insert to prevent two-way paths, e.g. 5 -> 7 && 7 -> 5
+        let mut edge_set = BTreeSet::new();
+        // sort vertex indices before insert to prevent two-way paths, e.g. 5 -> 7 && 7 -> 5.
+        // The set is ordered so that the same model always gives the points in the same order
         for path in poly_paths {
             edge_set.insert((min(path[0], path[1]), max(path[0], path[1])));
             edge_set.insert((min(path[1], path[2]), max(path[1], path[2])));
@@ -67,7 +97,7 @@ impl ObjInterpolator {
             // insert interpolated vertices
             for i in 0..=num_points {
                 let mut lerp_factor = (1.0 / num_points as f32) * i as f32;
-                if lerp_factor == INFINITY {
+                if lerp_factor == f32::INFINITY {
                     lerp_factor = 0.0;
                 }
                 let interpolated_vertex = start_vert_position.lerp(end_vert_position, lerp_factor);
@@ -75,7 +105,7 @@ impl ObjInterpolator {
             }
         }
 
-        return vertex_data;
+        vertex_data
     }
 
     #[allow(dead_code)]
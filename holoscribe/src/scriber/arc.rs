@@ -0,0 +1,202 @@
+use glam::{Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::{PI, TAU};
+use std::fmt;
+
+use super::{
+    circle_layer, CircleScriber, HoloPointStrategy, ImageKind, Num, ScribePlan, Shape,
+    ViewingGeometry,
+};
+
+/// The range of viewing directions the hologram should be visible from, as full angles in
+/// degrees around the nominal line of sight of a `ViewingGeometry`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewingCone {
+    pub horizontal: Num,
+    pub vertical: Num,
+}
+
+impl Default for ViewingCone {
+    fn default() -> Self {
+        Self {
+            horizontal: 30.0,
+            vertical: 20.0,
+        }
+    }
+}
+
+/// Direction on the plate (radians, counter-clockwise from +x with y towards the top of the
+/// plate) of the glint on a circle, seen from `horizontal`/`vertical` degrees off the nominal
+/// line of sight. `None` when light and viewer are mirror images of each other and every
+/// point of the circle glints at once.
+pub(crate) fn glint_direction(
+    geometry: &ViewingGeometry,
+    horizontal: Num,
+    vertical: Num,
+) -> Option<Num> {
+    let (h, v) = (horizontal.to_radians(), vertical.to_radians());
+    let viewer_angle = geometry.viewer_angle.to_radians() + v;
+    let light = Vec2::new(0.0, geometry.light_angle.to_radians().sin());
+    let viewer = Vec2::new(h.sin(), h.cos() * viewer_angle.sin());
+    let sum = light + viewer;
+    (sum.length() > 1e-3).then(|| sum.y.atan2(sum.x))
+}
+
+/// Start and end direction of the part of a circle that glints for some viewer inside the
+/// cone, with `end > start`. `None` when the whole circle has to be scribed.
+pub(crate) fn glint_span(geometry: &ViewingGeometry, cone: &ViewingCone) -> Option<(Num, Num)> {
+    let center = glint_direction(geometry, 0.0, 0.0)?;
+    let (mut min, mut max) = (0.0 as Num, 0.0 as Num);
+    for h in [-0.5, 0.0, 0.5] {
+        for v in [-0.5, 0.0, 0.5] {
+            let direction = glint_direction(geometry, h * cone.horizontal, v * cone.vertical)?;
+            // Offset from the center direction, wrapped into (-PI, PI]
+            let offset = (direction - center + PI).rem_euclid(TAU) - PI;
+            min = min.min(offset);
+            max = max.max(offset);
+        }
+    }
+    (max - min < PI).then_some((center + min, center + max))
+}
+
+/// `glint_span` for a point of the given image kind
+pub(crate) fn image_span(
+    geometry: &ViewingGeometry,
+    cone: &ViewingCone,
+    image: ImageKind,
+) -> Option<(Num, Num)> {
+    let (start, end) = glint_span(geometry, cone)?;
+    Some(match image {
+        ImageKind::Virtual => (start, end),
+        // Real images glint on the opposite side of the circle
+        ImageKind::Real => (start + PI, end + PI),
+    })
+}
+
+/// Total length in mm of the scribed lines compared with scribing full circles
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ScribeLengths {
+    pub full: Num,
+    pub scribed: Num,
+}
+
+impl ScribeLengths {
+    /// The lengths of the circles and arcs of a plan. Other shapes aren't counted
+    pub fn of(plan: &ScribePlan) -> Self {
+        let scale = plan.mm_per_unit();
+        plan.primitives
+            .iter()
+            .fold(Self::default(), |mut lengths, primitive| {
+                let (radius, sweep) = match primitive.shape {
+                    Shape::Circle { radius, .. } => (radius, TAU),
+                    Shape::Arc { radius, sweep, .. } => (radius, sweep.abs()),
+                    _ => return lengths,
+                };
+                lengths.full += TAU * radius * scale;
+                lengths.scribed += sweep * radius * scale;
+                lengths
+            })
+    }
+
+    pub fn saved(&self) -> Num {
+        self.full - self.scribed
+    }
+}
+
+impl fmt::Display for ScribeLengths {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percentage = if self.full > 0.0 {
+            100.0 * self.saved() / self.full
+        } else {
+            0.0
+        };
+        write!(
+            f,
+            "scribed length {:.2} of {:.2} mm ({:.2} mm, {:.1}% saved)",
+            self.scribed,
+            self.full,
+            self.saved(),
+            percentage
+        )
+    }
+}
+
+/// Scribes only the part of each circle that glints for a viewer inside the viewing cone.
+pub struct ArcScriber {
+    circles: CircleScriber,
+    geometry: ViewingGeometry,
+    cone: ViewingCone,
+}
+
+impl ArcScriber {
+    /// Circle sizes come from `circles`, the position of the arcs on them from the
+    /// viewer and light angles of `geometry`
+    pub fn new(circles: CircleScriber, geometry: ViewingGeometry, cone: ViewingCone) -> Self {
+        Self {
+            circles,
+            geometry,
+            cone,
+        }
+    }
+
+    /// Start and end direction of the arc scribed for a point of the given image kind
+    fn span(&self, image: ImageKind) -> Option<(Num, Num)> {
+        image_span(&self.geometry, &self.cone, image)
+    }
+}
+
+impl HoloPointStrategy for ArcScriber {
+    /// This is a scriber that draws the visible arc of each circle, in the same layers as the circles of
+    /// `CircleScriber`. Where the whole circle is visible it draws the circle.
+    fn scribe_points(&self, points: &[Vec3], plan: &mut ScribePlan) {
+        let scale = plan.mm_per_unit();
+        for &point in points {
+            let (radius, image) = self.circles.circle(point, scale);
+            let center = point.truncate();
+            let shape = match self.span(image) {
+                // Directions are measured with y towards the top of the plate, which is -y in the plan,
+                // so the arc runs backwards from -end
+                Some((start, end)) => Shape::Arc {
+                    center,
+                    radius,
+                    start: -end,
+                    sweep: end - start,
+                },
+                None => Shape::Circle { center, radius },
+            };
+            plan.push(
+                shape,
+                self.circles.depth(point, scale),
+                image,
+                circle_layer(image),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glint_span() {
+        // Lit from above, looked at straight on: the glint is at the top of the circle and
+        // moves sideways with the viewer
+        let geometry = ViewingGeometry::default();
+        let cone = ViewingCone {
+            horizontal: 30.0,
+            vertical: 0.0,
+        };
+        let (start, end) = glint_span(&geometry, &cone).unwrap();
+        assert!((start + end - PI).abs() < 1e-4);
+        assert!(end - start > 0.0 && end - start < PI);
+
+        // Light straight on and behind the viewer: the glint can be anywhere on the circle
+        let geometry = ViewingGeometry {
+            light_angle: 0.0,
+            ..geometry
+        };
+        assert_eq!(glint_span(&geometry, &cone), None);
+    }
+}
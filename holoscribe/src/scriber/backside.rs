@@ -0,0 +1,102 @@
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+
+use super::{Num, ScribePlan};
+
+/// The line through the center of the canvas a plan is mirrored about
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MirrorAxis {
+    /// Left and right swap, for a plate turned over about its vertical center line
+    #[default]
+    Vertical,
+    /// Top and bottom swap, for a plate turned over about its horizontal center line
+    Horizontal,
+}
+
+/// Scribing on the back of a transparent plate, so that the front stays glossy.
+///
+/// The plate is scribed lying face down, so the pattern is mirrored. Seen through the front,
+/// the scratches sit `thickness` mm behind the surface, and refraction makes everything
+/// behind it appear `refractive_index` times shallower than it is: an image `d` mm behind
+/// the scratches appears `(thickness + d) / refractive_index` mm behind the front surface.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackSide {
+    pub axis: MirrorAxis,
+    /// Thickness of the plate in mm
+    pub thickness: Num,
+    pub refractive_index: Num,
+}
+
+impl Default for BackSide {
+    fn default() -> Self {
+        // 3mm acrylic
+        Self {
+            axis: MirrorAxis::default(),
+            thickness: 3.0,
+            refractive_index: 1.49,
+        }
+    }
+}
+
+impl BackSide {
+    /// Depth in mm behind the scratches of the image that appears `depth` mm behind the front
+    /// surface. Points closer than `thickness / refractive_index` behind the front become real
+    /// images in front of the scratches
+    pub fn scribe_depth(&self, depth: Num) -> Num {
+        depth * self.refractive_index - self.thickness
+    }
+
+    /// Mirror the plan about the axis through the center of its canvas, as it is seen from
+    /// the back of the plate
+    pub fn mirror(&self, plan: &mut ScribePlan) {
+        let center = plan.from_mm(Vec2::from(plan.canvas_size) / 2.0);
+        let at = match self.axis {
+            MirrorAxis::Vertical => center.x,
+            MirrorAxis::Horizontal => center.y,
+        };
+        for primitive in &mut plan.primitives {
+            primitive.shape = primitive.shape.mirrored(self.axis, at);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scriber::{ImageKind, Rect, Shape};
+
+    #[test]
+    fn test_back_side() {
+        let back_side = BackSide {
+            thickness: 2.0,
+            refractive_index: 1.5,
+            ..Default::default()
+        };
+        // The plate looks 1.33mm thick, and an image 4mm behind the scratches another 2.67mm
+        // deep, appearing 4mm behind the front
+        assert_eq!(back_side.scribe_depth(4.0), 4.0);
+        assert!(back_side.scribe_depth(1.0) < 0.0);
+
+        let mut plan = ScribePlan::new(Rect::new(0.0, 0.0, 10.0, 10.0), (100.0, 100.0));
+        let arc = Shape::Arc {
+            center: Vec2::new(2.0, 3.0),
+            radius: 1.0,
+            start: 0.0,
+            sweep: 1.0,
+        };
+        plan.push(arc.clone(), 1.0, ImageKind::Virtual, "arcs");
+        back_side.mirror(&mut plan);
+        let (start, end) = plan.primitives[0].shape.endpoints();
+        let (arc_start, arc_end) = arc.endpoints();
+        let mirror = |point: Vec2| Vec2::new(10.0 - point.x, point.y);
+        assert!(start.distance(mirror(arc_start)) < 1e-5);
+        assert!(end.distance(mirror(arc_end)) < 1e-5);
+
+        // Mirroring twice gives back the original
+        back_side.mirror(&mut plan);
+        let (start, _) = plan.primitives[0].shape.endpoints();
+        assert!(start.distance(arc_start) < 1e-5);
+    }
+}
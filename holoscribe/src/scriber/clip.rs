@@ -0,0 +1,336 @@
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::TAU;
+use std::fmt;
+
+use super::outline::Boundary;
+use super::{Num, Outline, Primitive, ScribePlan, Shape};
+
+/// The part of the canvas that can be scribed: inside the plate outline and outside every
+/// mask, such as mounting holes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlateClip {
+    /// Outline of the plate. `None` keeps everything outside the masks
+    pub outline: Option<Outline>,
+    pub masks: Vec<Outline>,
+    /// Maximum deviation in mm of the polylines replacing clipped curves other than circles
+    /// and arcs
+    pub tolerance: Num,
+}
+
+/// How much of a plan was clipped away, in mm of scribed line
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ClipReport {
+    pub length_before: Num,
+    pub length_after: Num,
+    /// Primitives left out completely
+    pub removed: usize,
+    /// Primitives cut into parts
+    pub split: usize,
+}
+
+impl ClipReport {
+    pub fn removed_length(&self) -> Num {
+        self.length_before - self.length_after
+    }
+}
+
+impl fmt::Display for ClipReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percentage = if self.length_before > 0.0 {
+            100.0 * self.removed_length() / self.length_before
+        } else {
+            0.0
+        };
+        write!(
+            f,
+            "clipped {:.2} of {:.2} mm ({:.1}%), {} shapes removed and {} split",
+            self.removed_length(),
+            self.length_before,
+            percentage,
+            self.removed,
+            self.split
+        )
+    }
+}
+
+/// The scribable area in the units of one plan
+struct Area {
+    outline: Option<Outline>,
+    masks: Vec<Outline>,
+    boundaries: Vec<Boundary>,
+}
+
+impl Area {
+    fn contains(&self, point: Vec2) -> bool {
+        self.outline
+            .as_ref()
+            .is_none_or(|outline| outline.contains(point))
+            && !self.masks.iter().any(|mask| mask.contains(point))
+    }
+}
+
+impl Default for PlateClip {
+    fn default() -> Self {
+        Self {
+            outline: None,
+            masks: Vec::new(),
+            tolerance: 0.01,
+        }
+    }
+}
+
+impl PlateClip {
+    pub fn new(outline: Option<Outline>, masks: Vec<Outline>) -> Self {
+        Self {
+            outline,
+            masks,
+            ..Default::default()
+        }
+    }
+
+    /// Whether there is anything to clip
+    pub fn is_empty(&self) -> bool {
+        self.outline.is_none() && self.masks.is_empty()
+    }
+
+    /// Cut away everything outside the scribable area. Circles and arcs are cut into arcs,
+    /// other shapes into polylines. The scribing order is kept.
+    pub fn clip(&self, plan: &mut ScribePlan) -> ClipReport {
+        let outline = self.outline.as_ref().map(|outline| outline.to_units(plan));
+        let masks: Vec<Outline> = self.masks.iter().map(|mask| mask.to_units(plan)).collect();
+        let boundaries = outline
+            .iter()
+            .chain(&masks)
+            .flat_map(|outline| outline.boundaries())
+            .collect();
+        let area = Area {
+            outline,
+            masks,
+            boundaries,
+        };
+
+        let scale = plan.mm_per_unit();
+        let tolerance = self.tolerance / scale;
+        let mut report = ClipReport::default();
+        let mut primitives = plan.primitives.clone();
+        primitives.sort_by_key(|primitive| primitive.order);
+        plan.primitives.clear();
+        for primitive in primitives {
+            let before = length(&primitive.shape, tolerance);
+            let parts = clip_shape(&primitive.shape, &area, tolerance);
+            report.length_before += before * scale;
+            match parts.as_slice() {
+                [] => report.removed += 1,
+                [part] if *part == primitive.shape => {}
+                _ => report.split += 1,
+            }
+            for shape in parts {
+                report.length_after += length(&shape, tolerance) * scale;
+                plan.primitives.push(Primitive {
+                    shape,
+                    order: plan.primitives.len(),
+                    ..primitive.clone()
+                });
+            }
+        }
+        report
+    }
+}
+
+/// The parts of a shape inside the area
+fn clip_shape(shape: &Shape, area: &Area, tolerance: Num) -> Vec<Shape> {
+    match *shape {
+        Shape::Circle { center, radius } => clip_arc(center, radius, 0.0, TAU, area)
+            .into_iter()
+            .map(|(start, sweep)| {
+                if sweep.abs() >= TAU {
+                    Shape::Circle { center, radius }
+                } else {
+                    Shape::Arc {
+                        center,
+                        radius,
+                        start,
+                        sweep,
+                    }
+                }
+            })
+            .collect(),
+        Shape::Arc {
+            center,
+            radius,
+            start,
+            sweep,
+        } => {
+            let parts = clip_arc(center, radius, start, sweep, area);
+            if let [(part_start, part_sweep)] = parts.as_slice() {
+                if *part_start == start && *part_sweep == sweep {
+                    return vec![shape.clone()];
+                }
+            }
+            parts
+                .into_iter()
+                .map(|(start, sweep)| Shape::Arc {
+                    center,
+                    radius,
+                    start,
+                    sweep,
+                })
+                .collect()
+        }
+        _ => {
+            let points = shape.to_polyline(tolerance);
+            let parts = clip_polyline(&points, area);
+            // Keep curves that are completely inside as they are
+            if parts.len() == 1 && parts[0].len() == points.len() {
+                return vec![shape.clone()];
+            }
+            parts
+                .into_iter()
+                .map(|points| Shape::Polyline { points })
+                .collect()
+        }
+    }
+}
+
+/// Start and sweep of the parts of an arc inside the area
+fn clip_arc(center: Vec2, radius: Num, start: Num, sweep: Num, area: &Area) -> Vec<(Num, Num)> {
+    let full = sweep.abs() >= TAU;
+    let at = |u: Num| {
+        let t = start + sweep * u;
+        center + radius * Vec2::new(t.cos(), t.sin())
+    };
+    // Fractions of the sweep where the arc may cross into or out of the area
+    let mut cuts: Vec<Num> = area
+        .boundaries
+        .iter()
+        .flat_map(|boundary| boundary.circle_crossings(center, radius))
+        .map(|angle| ((angle - start) * sweep.signum()).rem_euclid(TAU) / sweep.abs())
+        .filter(|u| *u > 0.0 && *u < 1.0)
+        .collect();
+    cuts.extend([0.0, 1.0]);
+    cuts.sort_by(Num::total_cmp);
+    cuts.dedup();
+
+    let mut parts: Vec<(Num, Num)> = Vec::new();
+    for pair in cuts.windows(2) {
+        let (from, to) = (pair[0], pair[1]);
+        if !area.contains(at((from + to) / 2.0)) {
+            continue;
+        }
+        match parts.last_mut() {
+            // Continue the previous part if it ends here
+            Some(last) if (last.0 + last.1 - from).abs() < 1e-6 => last.1 = to - last.0,
+            _ => parts.push((from, to - from)),
+        }
+    }
+    // A circle cut open at its start can be joined back up around it
+    if full && parts.len() > 1 {
+        let (first, last) = (parts[0], parts[parts.len() - 1]);
+        if first.0 == 0.0 && last.0 + last.1 == 1.0 {
+            parts.pop();
+            parts[0] = (last.0, last.1 + first.1);
+        }
+    }
+    parts
+        .into_iter()
+        .map(|(from, fraction)| (start + sweep * from, sweep * fraction))
+        .collect()
+}
+
+/// The runs of a polyline inside the area
+fn clip_polyline(points: &[Vec2], area: &Area) -> Vec<Vec<Vec2>> {
+    let mut parts = Vec::new();
+    let mut run: Vec<Vec2> = Vec::new();
+    for segment in points.windows(2) {
+        let (a, b) = (segment[0], segment[1]);
+        let mut cuts: Vec<Num> = area
+            .boundaries
+            .iter()
+            .flat_map(|boundary| boundary.segment_crossings(a, b))
+            .filter(|u| *u > 0.0 && *u < 1.0)
+            .collect();
+        cuts.extend([0.0, 1.0]);
+        cuts.sort_by(Num::total_cmp);
+        cuts.dedup();
+        for pair in cuts.windows(2) {
+            let (from, to) = (a.lerp(b, pair[0]), a.lerp(b, pair[1]));
+            if area.contains((from + to) / 2.0) {
+                if run.is_empty() {
+                    run.push(from);
+                }
+                run.push(to);
+            } else if run.len() > 1 {
+                parts.push(std::mem::take(&mut run));
+            } else {
+                run.clear();
+            }
+        }
+    }
+    if run.len() > 1 {
+        parts.push(run);
+    }
+    parts
+}
+
+/// Length of a shape in viewbox units
+fn length(shape: &Shape, tolerance: Num) -> Num {
+    match shape {
+        Shape::Circle { radius, .. } => TAU * radius,
+        Shape::Arc { radius, sweep, .. } => (radius * sweep).abs(),
+        shape => shape
+            .to_polyline(tolerance)
+            .windows(2)
+            .map(|pair| pair[0].distance(pair[1]))
+            .sum(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scriber::{ImageKind, Rect};
+    use std::f32::consts::PI;
+
+    #[test]
+    fn test_clip_to_circular_plate() {
+        // 1 unit is 10mm, the plate is a circle of radius 50mm in the middle of the canvas
+        let mut plan = ScribePlan::new(Rect::new(0.0, 0.0, 10.0, 10.0), (100.0, 100.0));
+        let circle = |x, y, radius| Shape::Circle {
+            center: Vec2::new(x, y),
+            radius,
+        };
+        plan.push(circle(5.0, 5.0, 1.0), 1.0, ImageKind::Virtual, "circles");
+        // Straddles the edge of the plate on the right
+        plan.push(circle(9.5, 5.0, 1.0), 1.0, ImageKind::Virtual, "circles");
+        plan.push(circle(0.5, 0.5, 0.2), 1.0, ImageKind::Virtual, "circles");
+        // Around a mounting hole of radius 5mm
+        plan.push(circle(5.0, 3.0, 1.0), 1.0, ImageKind::Virtual, "circles");
+
+        let clip = PlateClip::new(
+            Some(Outline::inscribed_circle(&plan)),
+            vec![Outline::Circle {
+                center: Vec2::new(50.0, 20.0),
+                radius: 5.0,
+            }],
+        );
+        let report = clip.clip(&mut plan);
+        assert_eq!(report.removed, 1);
+        assert_eq!(report.split, 2);
+        assert_eq!(plan.primitives[0].shape, circle(5.0, 5.0, 1.0));
+
+        // The larger left part of the straddling circle, symmetric around PI
+        let Shape::Arc { start, sweep, .. } = plan.primitives[1].shape else {
+            panic!("expected an arc");
+        };
+        assert!((start + sweep / 2.0 - PI).abs() < 1e-4);
+        assert!(sweep > PI && sweep < TAU);
+
+        // The circle through the hole is opened up where it passes it
+        assert_eq!(plan.primitives.len(), 3);
+        assert_eq!(plan.primitives[2].order, 2);
+        assert!(matches!(plan.primitives[2].shape, Shape::Arc { .. }));
+        assert!(report.removed_length() > 2.0 * PI * 2.0);
+    }
+}
@@ -0,0 +1,315 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use svg::Node;
+
+use super::{Intensity, Num, ScribePlan};
+
+/// Stroke width in viewbox units, which are units of the model, of a style without one
+const HAIRLINE_WIDTH: Num = 0.005;
+
+/// Stroke and fill used for the elements of a layer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Style {
+    pub stroke: String,
+    /// Width of the scribed line in millimeters on the finished plate. `None` draws a hairline
+    /// that scales with the model, as `Scriber` always used to
+    pub stroke_width: Option<Num>,
+    pub fill: String,
+}
+
+impl Style {
+    /// Width of the scribed line in millimeters, for a model scaled by `mm_per_unit`
+    pub fn stroke_width_mm(&self, mm_per_unit: Num) -> Num {
+        self.stroke_width.unwrap_or(HAIRLINE_WIDTH * mm_per_unit)
+    }
+
+    /// This style with the fields `layer` sets replaced
+    pub fn merged(&self, layer: &LayerStyle) -> Style {
+        Style {
+            stroke: layer.stroke.clone().unwrap_or_else(|| self.stroke.clone()),
+            stroke_width: layer.stroke_width.or(self.stroke_width),
+            fill: layer.fill.clone().unwrap_or_else(|| self.fill.clone()),
+        }
+    }
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            stroke: "black".to_string(),
+            stroke_width: None,
+            fill: "none".to_string(),
+        }
+    }
+}
+
+/// The fields of a layer's style that differ from the default style, in the same units as
+/// `Style`. Fields left out are taken from the default style
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayerStyle {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stroke: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stroke_width: Option<Num>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fill: Option<String>,
+}
+
+/// How the model is scaled onto the canvas
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum Fit {
+    /// As large as possible with the whole model on the canvas
+    Contain,
+    /// As small as possible with the whole canvas covered by the model, cropping the rest
+    Cover,
+    /// A fixed scale, regardless of the canvas size
+    Fixed { mm_per_unit: Num },
+}
+
+/// Where the model goes along an axis of the canvas when it doesn't fill it exactly
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Align {
+    /// Left or top
+    Start,
+    Center,
+    /// Right or bottom
+    End,
+}
+
+impl Align {
+    /// Offset that places something `size` long at this alignment in a space `space` long
+    pub fn offset(&self, space: Num, size: Num) -> Num {
+        match self {
+            Align::Start => 0.0,
+            Align::Center => (space - size) / 2.0,
+            Align::End => space - size,
+        }
+    }
+}
+
+/// What the primitives of a plan are grouped by in the output, overriding the groups of
+/// the source objects of the model
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "by", rename_all = "snake_case")]
+pub enum Grouping {
+    /// The layer the strategy drew the primitive into
+    Layer,
+    /// Bands of `band` mm of depth, named `depth-<n>` for depths from `n * band` mm
+    DepthBand { band: Num },
+    /// Every depth in the plan, which after quantizing the points is one per depth level,
+    /// named `level-<n>` from the smallest depth up
+    DepthLevel,
+}
+
+impl Grouping {
+    /// Put every primitive of the plan in its group
+    pub fn apply(&self, plan: &mut ScribePlan) {
+        // The distinct depths, for numbering the levels
+        let mut depths: Vec<Num> = plan
+            .primitives
+            .iter()
+            .map(|primitive| primitive.depth)
+            .collect();
+        depths.sort_by(Num::total_cmp);
+        depths.dedup();
+        for primitive in &mut plan.primitives {
+            primitive.group = Some(match self {
+                Grouping::Layer => primitive.layer.clone(),
+                Grouping::DepthBand { band } => {
+                    format!("depth-{}", (primitive.depth / band).floor())
+                }
+                Grouping::DepthLevel => {
+                    let level = depths.partition_point(|depth| *depth < primitive.depth);
+                    format!("level-{}", level + 1)
+                }
+            });
+        }
+    }
+}
+
+/// Appearance and geometry settings of a `Scriber`.
+///
+/// Every field has a default, so a config file only needs to list the values it changes:
+/// ```toml
+/// background = "white"
+/// margin_percentage = 0.1
+/// margin = 5.0
+/// align = ["center", "start"]
+///
+/// [fit]
+/// mode = "fixed"
+/// mm_per_unit = 20.0
+///
+/// [style]
+/// stroke = "black"
+/// stroke_width = 0.1
+///
+/// [layers.circles]
+/// stroke = "red"
+///
+/// [intensity]
+/// far = 1.0
+/// near = 0.6
+///
+/// [grouping]
+/// by = "depth_band"
+/// band = 2.0
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriberConfig {
+    /// Style of every layer, with the fields set in `layers` replaced
+    pub style: Style,
+    /// Per-layer overrides of `style`, keyed by the layer names the strategies draw into
+    pub layers: BTreeMap<String, LayerStyle>,
+    /// Fill color of the canvas. `None` leaves it transparent
+    pub background: Option<String>,
+    /// Margin added around the scribed lines, as a fraction of their width and height
+    pub margin_percentage: Num,
+    /// Margin in mm between the edge of the canvas and the model
+    pub margin: Num,
+    pub fit: Fit,
+    /// Horizontal and vertical alignment of the model inside the margins
+    pub align: (Align, Align),
+    /// How hard each primitive is scribed
+    pub intensity: Intensity,
+    /// What the output is grouped by. `None` keeps the groups of the source objects, if any
+    pub grouping: Option<Grouping>,
+}
+
+impl Default for ScriberConfig {
+    fn default() -> Self {
+        Self {
+            style: Style::default(),
+            layers: BTreeMap::new(),
+            background: None,
+            margin_percentage: 0.25,
+            margin: 0.0,
+            fit: Fit::Contain,
+            align: (Align::Center, Align::Center),
+            intensity: Intensity::default(),
+            grouping: None,
+        }
+    }
+}
+
+impl ScriberConfig {
+    /// The style of `layer`: the default style with the overrides of the layer, if any
+    pub fn style(&self, layer: &str) -> Style {
+        match self.layers.get(layer) {
+            Some(overrides) => self.style.merged(overrides),
+            None => self.style.clone(),
+        }
+    }
+}
+
+/// The styles of a `ScriberConfig` resolved for one document, with stroke widths
+/// converted from millimeters to viewbox units.
+pub struct LayerStyles<'a> {
+    config: &'a ScriberConfig,
+    units_per_mm: Num,
+}
+
+impl<'a> LayerStyles<'a> {
+    pub fn new(config: &'a ScriberConfig, units_per_mm: Num) -> Self {
+        Self {
+            config,
+            units_per_mm,
+        }
+    }
+
+    /// Set the stroke and fill attributes of `layer` on an element
+    pub fn apply<T: Node>(&self, layer: &str, mut node: T) -> T {
+        let style = self.config.style(layer);
+        node.assign("stroke", style.stroke.clone());
+        let stroke_width = style
+            .stroke_width
+            .map_or(HAIRLINE_WIDTH, |width| width * self.units_per_mm);
+        node.assign("stroke-width", stroke_width);
+        node.assign("fill", style.fill.clone());
+        node
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config: ScriberConfig = toml::from_str(
+            r#"
+            [style]
+            stroke = "black"
+            stroke_width = 0.1
+
+            [layers.circles]
+            stroke = "red"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.style.stroke_width, Some(0.1));
+        assert_eq!(config.style("debug"), config.style);
+        // The fields a layer leaves out come from `style`
+        let circles = config.style("circles");
+        assert_eq!(circles.stroke, "red");
+        assert_eq!(circles.stroke_width, Some(0.1));
+        assert_eq!(circles.fill, "none");
+        assert_eq!(
+            config.margin_percentage,
+            ScriberConfig::default().margin_percentage
+        );
+
+        // Without a width anywhere the lines are hairlines
+        let config: ScriberConfig = toml::from_str("[layers.circles]\nfill = \"red\"").unwrap();
+        assert_eq!(
+            config.style("circles").stroke_width_mm(0.1),
+            HAIRLINE_WIDTH * 0.1
+        );
+    }
+
+    #[test]
+    fn test_parse_config_example() {
+        // The example in the documentation of `ScriberConfig`
+        let config: ScriberConfig = toml::from_str(
+            r#"
+            background = "white"
+            margin_percentage = 0.1
+            margin = 5.0
+            align = ["center", "start"]
+
+            [fit]
+            mode = "fixed"
+            mm_per_unit = 20.0
+
+            [style]
+            stroke = "black"
+            stroke_width = 0.1
+
+            [layers.circles]
+            stroke = "red"
+
+            [intensity]
+            far = 1.0
+            near = 0.6
+
+            [grouping]
+            by = "depth_band"
+            band = 2.0
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.background.as_deref(), Some("white"));
+        assert_eq!((config.margin_percentage, config.margin), (0.1, 5.0));
+        assert_eq!(config.align, (Align::Center, Align::Start));
+        assert_eq!(config.fit, Fit::Fixed { mm_per_unit: 20.0 });
+        assert_eq!(config.style("circles").stroke, "red");
+        assert_eq!(config.style("circles").stroke_width, Some(0.1));
+        assert_eq!((config.intensity.far, config.intensity.near), (1.0, 0.6));
+        assert_eq!(config.grouping, Some(Grouping::DepthBand { band: 2.0 }));
+    }
+}
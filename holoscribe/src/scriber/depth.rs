@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+use super::Num;
+
+/// Where the viewer and the light source are relative to the plate.
+///
+/// Angles are measured from the plate normal in the vertical plane, positive towards the
+/// top of the plate. A plate hung on a wall, looked at straight on and lit from above and
+/// in front has `viewer_angle = 0` and `light_angle` around 45.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewingGeometry {
+    /// Distance from the plate to the viewer's eyes in mm
+    pub viewer_distance: Num,
+    /// Angle of the line of sight in degrees
+    pub viewer_angle: Num,
+    /// Angle of the light source in degrees
+    pub light_angle: Num,
+}
+
+impl Default for ViewingGeometry {
+    fn default() -> Self {
+        Self {
+            viewer_distance: 500.0,
+            viewer_angle: 0.0,
+            light_angle: 45.0,
+        }
+    }
+}
+
+impl ViewingGeometry {
+    /// Radius in mm of the scratch that shows a point `depth` mm behind the plate.
+    ///
+    /// A circular scratch glints where its radius points along the plate projection of the
+    /// sum of the unit vectors towards the light and the viewer. When the viewer steps
+    /// sideways by `dx`, that direction turns by `dx / (V * (sin a_v + sin a_l))`, moving the
+    /// glint by `r` times that angle. A point at depth `d` moves by `dx * d / (V cos a_v + d)`
+    /// on the plate, and equating the two gives
+    ///
+    /// `r = V (sin a_v + sin a_l) d / (V cos a_v + d)`
+    ///
+    /// Points in front of the plate (negative depth) get a negative radius: the glint sits on
+    /// the opposite side of the circle. Points can't come closer than 95% of the way to the
+    /// viewer and are clamped there.
+    pub fn radius_mm(&self, depth: Num) -> Num {
+        let (a_v, a_l) = (
+            self.viewer_angle.to_radians(),
+            self.light_angle.to_radians(),
+        );
+        let normal_distance = self.viewer_distance * a_v.cos();
+        let depth = depth.max(-0.95 * normal_distance);
+        self.viewer_distance * (a_v.sin() + a_l.sin()) * depth / (normal_distance + depth)
+    }
+}
+
+/// How the depth of a point is turned into the radius of its circle, both in mm
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "curve", rename_all = "snake_case")]
+pub enum DepthMapping {
+    /// `r = scale * z`
+    Linear { scale: Num },
+    /// `r = scale * |z|^gamma`, keeping the sign of z
+    Gamma { scale: Num, gamma: Num },
+    /// Linear interpolation between calibrated `(z, r)` pairs, sorted by z.
+    /// Depths outside the table are clamped to its ends
+    Lookup { table: Vec<(Num, Num)> },
+    /// Radius worked out from the viewing geometry, so that z is the apparent depth
+    Physical(ViewingGeometry),
+}
+
+impl Default for DepthMapping {
+    fn default() -> Self {
+        DepthMapping::Linear { scale: 0.25 }
+    }
+}
+
+impl DepthMapping {
+    pub fn lookup(mut table: Vec<(Num, Num)>) -> Self {
+        table.sort_by(|a, b| a.0.total_cmp(&b.0));
+        DepthMapping::Lookup { table }
+    }
+
+    // load a lookup table from a csv file with a z and a radius column
+    pub fn lookup_from_csv(file_path: String) -> Result<Self, Box<dyn Error>> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .trim(csv::Trim::All)
+            .from_path(file_path)?;
+        let mut table = Vec::new();
+        for record in reader.records() {
+            let record = record?;
+            if record.len() != 2 {
+                return Err(format!("expected 2 columns, found {}", record.len()).into());
+            }
+            table.push((record[0].parse()?, record[1].parse()?));
+        }
+        if table.is_empty() {
+            return Err("empty lookup table".into());
+        }
+        Ok(Self::lookup(table))
+    }
+
+    /// Radius of the circle for a point `z` mm behind the plate. Negative radii are points in
+    /// front of the plate.
+    pub fn radius(&self, z: Num) -> Num {
+        match self {
+            DepthMapping::Linear { scale } => z * scale,
+            DepthMapping::Gamma { scale, gamma } => z.signum() * scale * z.abs().powf(*gamma),
+            DepthMapping::Lookup { table } => interpolate(table, z),
+            DepthMapping::Physical(geometry) => geometry.radius_mm(z),
+        }
+    }
+}
+
+fn interpolate(table: &[(Num, Num)], z: Num) -> Num {
+    let upper = table.partition_point(|&(tz, _)| tz < z);
+    if upper == 0 {
+        return table[0].1;
+    }
+    if upper == table.len() {
+        return table[table.len() - 1].1;
+    }
+    let ((z0, r0), (z1, r1)) = (table[upper - 1], table[upper]);
+    r0 + (z - z0) * (r1 - r0) / (z1 - z0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_physical_radius() {
+        // Lit from directly above the viewer's line of sight, far away: r ~ d * sin(a_l) * V / V
+        let geometry = ViewingGeometry {
+            viewer_distance: 1e6,
+            viewer_angle: 0.0,
+            light_angle: 90.0,
+        };
+        assert!((geometry.radius_mm(5.0) - 5.0).abs() < 1e-3);
+
+        // A closer viewer sees more parallax, so the same depth needs a smaller circle
+        let geometry = ViewingGeometry::default();
+        let r = geometry.radius_mm(5.0);
+        assert!((r - 500.0 * 45f32.to_radians().sin() * 5.0 / 505.0).abs() < 1e-4);
+        assert!(geometry.radius_mm(-5.0) < 0.0);
+        assert_eq!(geometry.radius_mm(0.0), 0.0);
+        assert_eq!(DepthMapping::Physical(geometry).radius(5.0), r);
+    }
+
+    #[test]
+    fn test_lookup_radius() {
+        let mapping = DepthMapping::lookup(vec![(1.0, 2.0), (0.0, 0.0), (2.0, 3.0)]);
+        assert_eq!(mapping.radius(0.5), 1.0);
+        assert_eq!(mapping.radius(1.5), 2.5);
+        assert_eq!(mapping.radius(-1.0), 0.0);
+        assert_eq!(mapping.radius(5.0), 3.0);
+    }
+
+    #[test]
+    fn test_gamma_radius() {
+        let mapping = DepthMapping::Gamma {
+            scale: 2.0,
+            gamma: 2.0,
+        };
+        assert_eq!(mapping.radius(3.0), 18.0);
+        assert_eq!(mapping.radius(-3.0), -18.0);
+    }
+}
@@ -0,0 +1,242 @@
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use super::{ImageKind, Num, Rect, ScribePlan, Shape};
+
+/// Layer and group fiducials are drawn into
+pub const FIDUCIAL_LAYER: &str = "fiducials";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FiducialKind {
+    /// A cross near a corner of the canvas
+    CornerCross,
+    /// Lines along the edges of the scribe area, just outside one of its corners
+    CropMark,
+    /// Ticks in the middle of the edges of the canvas, pointing at its center
+    CenterMark,
+    /// A bar of known length along the bottom edge of the canvas
+    ScaleBar,
+}
+
+impl FiducialKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FiducialKind::CornerCross => "corner_cross",
+            FiducialKind::CropMark => "crop_mark",
+            FiducialKind::CenterMark => "center_mark",
+            FiducialKind::ScaleBar => "scale_bar",
+        }
+    }
+}
+
+/// A mark for lining a plate up, in mm from the top left corner of the canvas
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Fiducial {
+    pub kind: FiducialKind,
+    /// The center of a cross, the corner of the scribe area of a crop mark, the center of
+    /// the canvas for a center mark, or the left end of a scale bar
+    pub position: Vec2,
+    /// Length of the arms of a mark, or of a scale bar
+    pub size: Num,
+}
+
+impl fmt::Display for Fiducial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at {:.2}, {:.2} mm, {:.2} mm",
+            self.kind.as_str(),
+            self.position.x,
+            self.position.y,
+            self.size
+        )
+    }
+}
+
+/// Which marks are scribed around the scribe area for lining the plate up between rigs.
+/// Marks that would overlap the scribed lines or fall off the canvas are left out.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Fiducials {
+    pub corner_crosses: bool,
+    pub crop_marks: bool,
+    pub center_mark: bool,
+    /// Length of the scale bar in mm, `None` for none
+    pub scale_bar: Option<Num>,
+    /// Length in mm of the arms of crosses and crop marks and of the center ticks
+    pub size: Num,
+    /// Distance in mm of the marks from the edges of the canvas and the scribe area
+    pub inset: Num,
+}
+
+impl Default for Fiducials {
+    fn default() -> Self {
+        Self {
+            corner_crosses: true,
+            crop_marks: true,
+            center_mark: true,
+            scale_bar: Some(10.0),
+            size: 4.0,
+            inset: 2.0,
+        }
+    }
+}
+
+impl Fiducials {
+    /// Add the marks to the plan, and their positions to its fiducials. Returns how many
+    /// marks were left out
+    pub fn apply(&self, plan: &mut ScribePlan) -> usize {
+        let area = plan.bounds().and_then(|bounds| {
+            Rect::from_points([plan.to_mm(bounds.min()), plan.to_mm(bounds.max())])
+        });
+        let canvas = Rect::new(0.0, 0.0, plan.canvas_size.0, plan.canvas_size.1);
+        let mut skipped = 0;
+        for (fiducial, lines) in self.marks(&canvas, area.as_ref()) {
+            let fits = lines.iter().all(|line| {
+                let bounds = Rect::from_points(*line).expect("lines have two points");
+                let on_canvas = canvas.min().cmple(bounds.min()).all()
+                    && bounds.max().cmple(canvas.max()).all();
+                on_canvas && area.as_ref().is_none_or(|area| !overlaps(area, &bounds))
+            });
+            if !fits {
+                skipped += 1;
+                continue;
+            }
+            for line in lines {
+                let points = line.iter().map(|point| plan.from_mm(*point)).collect();
+                plan.push(
+                    Shape::Polyline { points },
+                    0.0,
+                    ImageKind::Virtual,
+                    FIDUCIAL_LAYER,
+                );
+                plan.primitives.last_mut().expect("just pushed").group =
+                    Some(FIDUCIAL_LAYER.to_string());
+            }
+            plan.fiducials.push(fiducial);
+        }
+        skipped
+    }
+
+    /// Every enabled fiducial with the lines drawing it, in mm
+    fn marks(&self, canvas: &Rect, area: Option<&Rect>) -> Vec<(Fiducial, Vec<[Vec2; 2]>)> {
+        let (size, inset) = (self.size, self.inset);
+        let (width, height) = (canvas.width, canvas.height);
+        let mut marks = Vec::new();
+        let mark = |kind, position, size| Fiducial {
+            kind,
+            position,
+            size,
+        };
+
+        if self.corner_crosses {
+            let near = inset + size;
+            for (x, y) in [
+                (near, near),
+                (width - near, near),
+                (near, height - near),
+                (width - near, height - near),
+            ] {
+                let center = Vec2::new(x, y);
+                let lines = vec![
+                    [center - Vec2::X * size, center + Vec2::X * size],
+                    [center - Vec2::Y * size, center + Vec2::Y * size],
+                ];
+                marks.push((mark(FiducialKind::CornerCross, center, size), lines));
+            }
+        }
+        if let (true, Some(area)) = (self.crop_marks, area) {
+            let (min, max) = (area.min(), area.max());
+            for corner in [min, Vec2::new(max.x, min.y), Vec2::new(min.x, max.y), max] {
+                // Pointing away from the scribe area along both of its edges
+                let outward = Vec2::new(
+                    if corner.x == min.x { -1.0 } else { 1.0 },
+                    if corner.y == min.y { -1.0 } else { 1.0 },
+                );
+                let horizontal = corner + Vec2::X * outward.x * inset;
+                let vertical = corner + Vec2::Y * outward.y * inset;
+                let lines = vec![
+                    [horizontal, horizontal + Vec2::X * outward.x * size],
+                    [vertical, vertical + Vec2::Y * outward.y * size],
+                ];
+                marks.push((mark(FiducialKind::CropMark, corner, size), lines));
+            }
+        }
+        if self.center_mark {
+            let center = Vec2::new(width / 2.0, height / 2.0);
+            let lines = vec![
+                [Vec2::new(center.x, 0.0), Vec2::new(center.x, size)],
+                [
+                    Vec2::new(center.x, height),
+                    Vec2::new(center.x, height - size),
+                ],
+                [Vec2::new(0.0, center.y), Vec2::new(size, center.y)],
+                [
+                    Vec2::new(width, center.y),
+                    Vec2::new(width - size, center.y),
+                ],
+            ];
+            marks.push((mark(FiducialKind::CenterMark, center, size), lines));
+        }
+        if let Some(length) = self.scale_bar {
+            let start = Vec2::new(inset, height - inset);
+            let end = start + Vec2::X * length;
+            let tick = Vec2::Y * size / 2.0;
+            let lines = vec![[start, end], [start, start - tick], [end, end - tick]];
+            marks.push((mark(FiducialKind::ScaleBar, start, length), lines));
+        }
+        marks
+    }
+}
+
+/// Whether two rectangles share more than an edge
+fn overlaps(a: &Rect, b: &Rect) -> bool {
+    a.min().cmplt(b.max()).all() && b.min().cmplt(a.max()).all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fiducials_around_scribe_area() {
+        // 1 unit is 1mm, with a circle covering 40 to 60mm
+        let mut plan = ScribePlan::new(Rect::new(0.0, 0.0, 100.0, 100.0), (100.0, 100.0));
+        let circle = Shape::Circle {
+            center: Vec2::new(50.0, 50.0),
+            radius: 10.0,
+        };
+        plan.push(circle, 1.0, ImageKind::Virtual, "circles");
+
+        // A 120mm scale bar does not fit on the canvas
+        let fiducials = Fiducials {
+            scale_bar: Some(120.0),
+            ..Default::default()
+        };
+        assert_eq!(fiducials.apply(&mut plan), 1);
+        let kinds: Vec<FiducialKind> = plan.fiducials.iter().map(|f| f.kind).collect();
+        assert_eq!(kinds[..4], [FiducialKind::CornerCross; 4]);
+        assert_eq!(kinds[4..8], [FiducialKind::CropMark; 4]);
+        assert_eq!(kinds[8..], [FiducialKind::CenterMark]);
+        assert_eq!(plan.fiducials[0].position, Vec2::new(6.0, 6.0));
+        assert_eq!(plan.fiducials[7].position, Vec2::new(60.0, 60.0));
+        // Two lines per cross and crop mark and four center ticks
+        assert_eq!(plan.primitives.len(), 1 + 8 * 2 + 4);
+        assert!(plan.primitives[1..]
+            .iter()
+            .all(|primitive| primitive.group.as_deref() == Some(FIDUCIAL_LAYER)));
+
+        // Covering 3 to 97mm leaves no room for any of the marks
+        let mut plan = ScribePlan::new(Rect::new(0.0, 0.0, 100.0, 100.0), (100.0, 100.0));
+        let circle = Shape::Circle {
+            center: Vec2::new(50.0, 50.0),
+            radius: 47.0,
+        };
+        plan.push(circle, 1.0, ImageKind::Virtual, "circles");
+        assert_eq!(Fiducials::default().apply(&mut plan), 10);
+        assert!(plan.fiducials.is_empty());
+        assert_eq!(plan.primitives.len(), 1);
+    }
+}
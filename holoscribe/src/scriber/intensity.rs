@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::{Num, ScribePlan};
+
+/// How hard each primitive is scribed, from 0 to 1.
+///
+/// Points far from the plate get large circles whose glints look dimmer than those of near
+/// points. Scribing them deeper or with more power balances the image. The intensity is
+/// interpolated between `near` and `far` by how far the source point is from the plate,
+/// in front of it or behind it, relative to the nearest and farthest points of the plan.
+/// It is then multiplied by the weight of the primitive's layer and clamped to 0..=1.
+///
+/// ```toml
+/// [intensity]
+/// near = 0.6
+/// far = 1.0
+///
+/// [intensity.weights]
+/// real-circles = 0.8
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Intensity {
+    /// Intensity of the primitives of the point nearest to the plate
+    pub near: Num,
+    /// Intensity of the primitives of the point farthest from the plate
+    pub far: Num,
+    /// Exponent of the interpolation. Above 1 the intensity stays close to `near` for longer
+    pub gamma: Num,
+    /// Weight per layer. Layers without an entry have a weight of 1
+    pub weights: BTreeMap<String, Num>,
+}
+
+impl Default for Intensity {
+    fn default() -> Self {
+        Self {
+            near: 1.0,
+            far: 1.0,
+            gamma: 1.0,
+            weights: BTreeMap::new(),
+        }
+    }
+}
+
+impl Intensity {
+    /// Set the intensity of every primitive of the plan
+    pub fn apply(&self, plan: &mut ScribePlan) {
+        let distances = plan
+            .primitives
+            .iter()
+            .map(|primitive| primitive.depth.abs());
+        let nearest = distances.clone().fold(Num::INFINITY, Num::min);
+        let range = distances.fold(Num::NEG_INFINITY, Num::max) - nearest;
+        for primitive in &mut plan.primitives {
+            let t = if range > 0.0 {
+                (primitive.depth.abs() - nearest) / range
+            } else {
+                0.0
+            };
+            let weight = self.weights.get(&primitive.layer).unwrap_or(&1.0);
+            let intensity = self.near + (self.far - self.near) * t.powf(self.gamma);
+            primitive.intensity = (intensity * weight).clamp(0.0, 1.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scriber::{ImageKind, Rect, Shape};
+    use glam::Vec2;
+
+    #[test]
+    fn test_intensity_by_distance() {
+        let mut plan = ScribePlan::new(Rect::new(0.0, 0.0, 10.0, 10.0), (100.0, 100.0));
+        let circle = Shape::Circle {
+            center: Vec2::new(5.0, 5.0),
+            radius: 1.0,
+        };
+        plan.push(circle.clone(), 2.0, ImageKind::Virtual, "circles");
+        plan.push(circle.clone(), 10.0, ImageKind::Virtual, "circles");
+        plan.push(circle.clone(), -6.0, ImageKind::Real, "real-circles");
+        plan.push(circle, 6.0, ImageKind::Virtual, "circles");
+
+        let intensity = Intensity {
+            near: 0.5,
+            far: 1.0,
+            weights: BTreeMap::from([("real-circles".to_string(), 0.5)]),
+            ..Default::default()
+        };
+        intensity.apply(&mut plan);
+        let intensities: Vec<Num> = plan.primitives.iter().map(|p| p.intensity).collect();
+        assert_eq!(intensities, [0.5, 1.0, 0.375, 0.75]);
+    }
+}
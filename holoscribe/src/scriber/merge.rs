@@ -0,0 +1,182 @@
+use glam::Vec2;
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+use std::fmt;
+
+use super::{Num, Primitive, ScribePlan, Shape};
+
+/// How many circles and arcs a `CircleMerger` found to be redundant
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    /// Circles and arcs in the plan before merging
+    pub circles: usize,
+    pub removed: usize,
+}
+
+impl fmt::Display for MergeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percentage = if self.circles > 0 {
+            100.0 * self.removed as Num / self.circles as Num
+        } else {
+            0.0
+        };
+        write!(
+            f,
+            "merged away {} of {} circles and arcs ({:.1}%)",
+            self.removed, self.circles, percentage
+        )
+    }
+}
+
+/// Removes circles and arcs that would be scribed into the groove of an earlier one.
+///
+/// Two circles are the same groove when their centers and radii differ by less than the
+/// tolerance, typically the width of the tool. Arcs also need both ends to be that close,
+/// in either direction. Only primitives of the same group are merged. Earlier primitives are
+/// looked up in a grid of cells as large as the tolerance, so merging takes about linear time.
+pub struct CircleMerger {
+    tolerance: Num,
+}
+
+/// A circle or arc, with its ends unless it is a full turn, and its group
+struct Groove<'a> {
+    center: Vec2,
+    radius: Num,
+    ends: Option<(Vec2, Vec2)>,
+    group: Option<&'a str>,
+}
+
+impl<'a> Groove<'a> {
+    fn of(primitive: &'a Primitive) -> Option<Self> {
+        let group = primitive.group.as_deref();
+        match primitive.shape {
+            Shape::Circle { center, radius } => Some(Self {
+                center,
+                radius,
+                ends: None,
+                group,
+            }),
+            Shape::Arc {
+                center,
+                radius,
+                sweep,
+                ..
+            } => Some(Self {
+                center,
+                radius,
+                ends: (sweep.abs() < TAU).then(|| primitive.shape.endpoints()),
+                group,
+            }),
+            _ => None,
+        }
+    }
+
+    fn matches(&self, other: &Groove, tolerance: Num) -> bool {
+        let close = |a: Vec2, b: Vec2| a.distance(b) < tolerance;
+        self.group == other.group
+            && close(self.center, other.center)
+            && (self.radius - other.radius).abs() < tolerance
+            && match (self.ends, other.ends) {
+                (None, None) => true,
+                (Some((a, b)), Some((c, d))) => {
+                    (close(a, c) && close(b, d)) || (close(a, d) && close(b, c))
+                }
+                _ => false,
+            }
+    }
+}
+
+impl CircleMerger {
+    /// Merge circles closer than `tolerance` mm
+    pub fn new(tolerance: Num) -> Self {
+        Self { tolerance }
+    }
+
+    /// Remove the redundant circles and arcs of the plan, keeping the first of each groove in
+    /// scribing order
+    pub fn merge(&self, plan: &mut ScribePlan) -> MergeReport {
+        if self.tolerance <= 0.0 {
+            return MergeReport {
+                circles: plan.primitives.iter().filter_map(Groove::of).count(),
+                removed: 0,
+            };
+        }
+        let tolerance = self.tolerance / plan.mm_per_unit();
+        let cell = |point: Vec2| {
+            let cell = (point / tolerance).floor();
+            (cell.x as i64, cell.y as i64)
+        };
+
+        plan.primitives.sort_by_key(|primitive| primitive.order);
+        let mut report = MergeReport::default();
+        let mut redundant = vec![false; plan.primitives.len()];
+        let mut grid: HashMap<(i64, i64), Vec<Groove>> = HashMap::new();
+        for (index, primitive) in plan.primitives.iter().enumerate() {
+            let Some(groove) = Groove::of(primitive) else {
+                continue;
+            };
+            report.circles += 1;
+            let (x, y) = cell(groove.center);
+            let merged = (x - 1..=x + 1)
+                .flat_map(|x| (y - 1..=y + 1).map(move |y| (x, y)))
+                .filter_map(|key| grid.get(&key))
+                .flatten()
+                .any(|other| groove.matches(other, tolerance));
+            if merged {
+                redundant[index] = true;
+                report.removed += 1;
+            } else {
+                grid.entry((x, y)).or_default().push(groove);
+            }
+        }
+
+        let mut redundant = redundant.into_iter();
+        plan.primitives
+            .retain(|_| !redundant.next().unwrap_or(false));
+        for (order, primitive) in plan.primitives.iter_mut().enumerate() {
+            primitive.order = order;
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scriber::{ImageKind, Rect};
+
+    #[test]
+    fn test_merge_circles() {
+        // 1 unit is 10mm, so a tolerance of 0.5mm is 0.05 units
+        let mut plan = ScribePlan::new(Rect::new(0.0, 0.0, 10.0, 10.0), (100.0, 100.0));
+        let circle = |x, radius| Shape::Circle {
+            center: Vec2::new(x, 5.0),
+            radius,
+        };
+        plan.push(circle(5.0, 1.0), 1.0, ImageKind::Virtual, "circles");
+        plan.push(circle(5.03, 1.02), 1.0, ImageKind::Virtual, "circles");
+        // Too large a radius, and too far away
+        plan.push(circle(5.0, 1.1), 1.0, ImageKind::Virtual, "circles");
+        plan.push(circle(5.06, 1.0), 1.0, ImageKind::Virtual, "circles");
+        // The same arc in the other direction
+        let arc = Shape::Arc {
+            center: Vec2::new(2.0, 2.0),
+            radius: 1.0,
+            start: 0.0,
+            sweep: 1.0,
+        };
+        plan.push(arc.clone(), 1.0, ImageKind::Virtual, "arcs");
+        plan.push(arc.reversed(), 1.0, ImageKind::Virtual, "arcs");
+
+        let report = CircleMerger::new(0.5).merge(&mut plan);
+        assert_eq!(
+            report,
+            MergeReport {
+                circles: 6,
+                removed: 2
+            }
+        );
+        assert_eq!(plan.primitives[1].shape, circle(5.0, 1.1));
+        assert_eq!(plan.primitives[3].order, 3);
+    }
+}
@@ -1,10 +1,83 @@
-use glam::Vec3;
-use svg::node::element::path::Data;
-use svg::node::element::{Circle, Path, SVG};
-use svg::Document;
+mod arc;
+mod backside;
+mod clip;
+mod config;
+mod depth;
+mod fiducial;
+mod intensity;
+mod merge;
+mod oblique;
+mod outline;
+mod plan;
+mod quantize;
+mod tile;
+mod toolpath;
+
+use glam::{Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+
+pub use arc::{ArcScriber, ScribeLengths, ViewingCone};
+pub use backside::{BackSide, MirrorAxis};
+pub use clip::{ClipReport, PlateClip};
+pub use config::{Align, Fit, Grouping, LayerStyle, LayerStyles, ScriberConfig, Style};
+pub use depth::{DepthMapping, ViewingGeometry};
+pub use fiducial::{Fiducial, FiducialKind, Fiducials, FIDUCIAL_LAYER};
+pub use intensity::Intensity;
+pub use merge::{CircleMerger, MergeReport};
+pub use oblique::{EllipseScriber, ParabolaScriber};
+pub use outline::Outline;
+pub use plan::{Primitive, Rect, ScribePlan, Shape};
+pub use quantize::{DepthLevel, DepthQuantizer, LevelMethod, QuantizeReport};
+pub use tile::{Tile, Tiling, REGISTRATION_LAYER};
+pub use toolpath::{ToolpathOptimizer, TravelReport};
+
+use crate::export::{Provenance, SvgExporter};
 
 type Num = f32;
 
+/// How many points are scribed at a time when the primitives are not kept
+const CHUNK_POINTS: usize = 4096;
+
+/// How many times `Scriber` refines the scale to fit the scribed geometry onto the canvas.
+/// Every round scribes all points once more, see `Scriber::find_extent`
+const MAX_LAYOUT_ROUNDS: usize = 8;
+
+/// Layer drawn into by `DebugScriber`
+pub const DEBUG_LAYER: &str = "debug";
+/// Layer drawn into by `CircleScriber` for points behind the reference plane
+pub const CIRCLE_LAYER: &str = "circles";
+/// Layer drawn into by `CircleScriber` for points in front of the reference plane
+pub const REAL_CIRCLE_LAYER: &str = "real-circles";
+
+/// Where the image of a point appears relative to the plate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageKind {
+    /// Behind the plate. The glint sits on the side of the circle facing the light
+    Virtual,
+    /// In front of the plate. The curvature is reversed and the glint sits on the side
+    /// facing away from the light
+    Real,
+}
+
+impl ImageKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImageKind::Virtual => "virtual",
+            ImageKind::Real => "real",
+        }
+    }
+}
+
+/// The layer circles (and the curves replacing them) are drawn into for an image kind
+fn circle_layer(image: ImageKind) -> &'static str {
+    match image {
+        ImageKind::Virtual => CIRCLE_LAYER,
+        ImageKind::Real => REAL_CIRCLE_LAYER,
+    }
+}
+
 pub struct DebugScriber {
     pub plane_start: Num,
     pub plane_end: Num,
@@ -20,137 +93,381 @@ impl DebugScriber {
     }
 }
 impl HoloPointStrategy for DebugScriber {
-    /// This is a scriber that uses points to construct `Data` to build a `Path` and add it to a `SVG` document. It draws
-    /// diamonds around the supplied points
-    fn scribe_points(&self, viewbox: SVG, points: &Vec<Vec3>, scriber: &Scriber) -> SVG {
-        let data = points.into_iter().fold(Data::new(), |d, &point| {
-            let (x, y, z) = (point.x, point.y, point.z);
-            let z = self.map_range(z);
-            d.move_to((x, y))
-                .move_by((0, -z))
-                .line_by((-z, z))
-                .line_by((z, z))
-                .line_by((z, -z))
-                .line_by((-z, -z))
-        });
-
-        let path = Path::new()
-            .set("fill", scriber.fill)
-            .set("stroke", scriber.stroke)
-            .set("stroke-width", scriber.stroke_width)
-            .set("d", data);
-        viewbox.add(path)
+    /// This is a scriber that draws a closed `Polyline` diamond around each of the supplied points
+    fn scribe_points(&self, points: &[Vec3], plan: &mut ScribePlan) {
+        let scale = plan.mm_per_unit();
+        for &point in points {
+            let center = point.truncate();
+            let z = self.map_range(point.z);
+            let diamond = [(0.0, -z), (-z, 0.0), (0.0, z), (z, 0.0), (0.0, -z)]
+                .map(|(x, y)| center + Vec2::new(x, y));
+            plan.push(
+                Shape::Polyline {
+                    points: diamond.to_vec(),
+                },
+                point.z * scale,
+                ImageKind::Virtual,
+                DEBUG_LAYER,
+            );
+        }
     }
 }
 
 pub struct CircleScriber {
-    z_scale_factor: f32,
+    mapping: DepthMapping,
+    reference_plane: Num,
+    back_side: Option<BackSide>,
 }
 
 impl CircleScriber {
     pub fn new() -> Self {
-        CircleScriber { z_scale_factor: 0.25 }
+        Self::with_mapping(DepthMapping::default())
+    }
+
+    pub fn with_mapping(mapping: DepthMapping) -> Self {
+        CircleScriber {
+            mapping,
+            reference_plane: 0.0,
+            back_side: None,
+        }
+    }
+
+    /// Set the z value of the plate surface. Points with a larger z are behind the plate,
+    /// points with a smaller z are in front of it.
+    pub fn reference_plane(mut self, z: Num) -> Self {
+        self.reference_plane = z;
+        self
+    }
+
+    /// Scribe on the back of a transparent plate. The reference plane stays the front surface,
+    /// and depths are corrected for looking at the scratches through the plate. Mirroring the
+    /// plan is up to `BackSide::mirror`
+    pub fn back_side(mut self, back_side: BackSide) -> Self {
+        self.back_side = Some(back_side);
+        self
+    }
+
+    /// Depth of a point behind the reference plane in mm, for a model scaled by `mm_per_unit`.
+    /// When scribing the back side, the depth behind the scratches
+    pub fn depth(&self, point: Vec3, mm_per_unit: Num) -> Num {
+        let depth = (point.z - self.reference_plane) * mm_per_unit;
+        match &self.back_side {
+            Some(back_side) => back_side.scribe_depth(depth),
+            None => depth,
+        }
+    }
+
+    /// The (non-negative) radius of the circle for a point in model units, and which side of
+    /// the plate its image appears on. The depth mapping works in mm, `mm_per_unit` converts
+    /// to and from them.
+    pub fn circle(&self, point: Vec3, mm_per_unit: Num) -> (Num, ImageKind) {
+        let radius = self.mapping.radius(self.depth(point, mm_per_unit)) / mm_per_unit;
+        if radius < 0.0 {
+            (-radius, ImageKind::Real)
+        } else {
+            (radius, ImageKind::Virtual)
+        }
+    }
+}
+
+impl Default for CircleScriber {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl HoloPointStrategy for CircleScriber {
-    /// This is a scriber that uses `Element`s (namely `Circle`s) and adds those directly to the `SVG` viewbox.
-    /// It draws circles around each point, scaled by the z value. (Larger z = farther away = larger circle with flatter arc)
-    fn scribe_points(&self, mut viewbox: SVG, points: &Vec<Vec3>, scriber: &Scriber) -> SVG {
-        for point in points {
-            let (x, y, z) = (point.x, point.y, point.z);
-            let circle = Circle::new()
-                .set("cx", x)
-                .set("cy", y)
-                .set("r", z * self.z_scale_factor as f32)
-                .set("stroke-width", scriber.stroke_width)
-                .set("stroke", scriber.stroke)
-                .set("fill", scriber.fill);
-
-            viewbox = viewbox.add(circle);
+    /// This is a scriber that draws circles around each point, sized from the z value by the depth mapping. (Larger z
+    /// = farther away = larger circle with flatter arc)
+    /// Points in front of the reference plane are real images: they are tagged as such and drawn into their own layer
+    /// instead of getting a negative radius.
+    fn scribe_points(&self, points: &[Vec3], plan: &mut ScribePlan) {
+        let scale = plan.mm_per_unit();
+        for &point in points {
+            let (radius, image) = self.circle(point, scale);
+            let circle = Shape::Circle {
+                center: point.truncate(),
+                radius,
+            };
+            plan.push(circle, self.depth(point, scale), image, circle_layer(image));
         }
-        viewbox
     }
 }
 
 /// Different strategies to visualize a point
 pub trait HoloPointStrategy {
-    fn scribe_points(&self, viewbox: SVG, points: &Vec<Vec3>, scriber: &Scriber) -> SVG;
+    /// Add the primitives scribing `points` to the plan
+    fn scribe_points(&self, points: &[Vec3], plan: &mut ScribePlan);
+
+    /// Bounding box in model units of what `scribe_points` adds for a model scaled by
+    /// `mm_per_unit`, `None` if it adds nothing. By default the points are scribed into a
+    /// scratch plan to find out, a chunk at a time.
+    fn bounds(&self, points: &[Vec3], mm_per_unit: Num) -> Option<Rect> {
+        // One unit of a 1x1 viewbox on a canvas `mm_per_unit` mm wide
+        let mut plan = ScribePlan::new(Rect::new(0.0, 0.0, 1.0, 1.0), (mm_per_unit, mm_per_unit));
+        let mut bounds: Option<Rect> = None;
+        for chunk in points.chunks(CHUNK_POINTS) {
+            plan.primitives.clear();
+            self.scribe_points(chunk, &mut plan);
+            bounds = match (bounds, plan.bounds()) {
+                (Some(a), Some(b)) => Some(a.union(&b)),
+                (a, b) => a.or(b),
+            };
+        }
+        bounds
+    }
 }
 
 pub struct Scriber {
-    stroke: &'static str,
-    stroke_width: Num,
-    fill: &'static str,
     point_scribing_strategy: Box<dyn HoloPointStrategy>,
     canvas_size: (usize, usize),
-    margin_percentage: Num,
+    config: ScriberConfig,
 }
 
-
 impl Scriber {
     pub fn new(
         point_scribing_strategy: impl HoloPointStrategy + 'static,
         canvas_size: (usize, usize),
+    ) -> Self {
+        Self::with_config(
+            point_scribing_strategy,
+            canvas_size,
+            ScriberConfig::default(),
+        )
+    }
+
+    pub fn with_config(
+        point_scribing_strategy: impl HoloPointStrategy + 'static,
+        canvas_size: (usize, usize),
+        config: ScriberConfig,
     ) -> Self {
         Self {
-            stroke: "black",
-            stroke_width: 0.005,
-            fill: "none",
             point_scribing_strategy: Box::new(point_scribing_strategy),
-            canvas_size: canvas_size,
-            margin_percentage: 0.25,
+            canvas_size,
+            config,
         }
     }
+
+    pub fn config(&self) -> &ScriberConfig {
+        &self.config
+    }
+
     /*
     Assumtions:
     - x points right
     - y points up
     - z is positive out of the screen
     */
-    pub fn scribe(&self, points: &Vec<Vec3>) -> svg::Document {
-        // Set the size of the containing document based on the canvas size supplied by the user
-        let doc = Document::new()
-            .set("width", self.canvas_size.0)
-            .set("height", self.canvas_size.1);
-
-        // Build a viewbox specified by the upper and lower bounds of the coordinates of the point set, plus
-        // some margin
-        let (x_min, y_min, width, height) = self.find_extent(points);
-        let mut viewbox = SVG::new().set("viewBox", (x_min,y_min, width, height));
-
-        // Scribe the points into the viewbox we just made
-        viewbox = self.point_scribing_strategy
-            .scribe_points(viewbox, points, &self);
-        doc.add(viewbox)
-    }
-
-    /// Returns (min_x, min_y, width, height) of the point set.
-    /// The values are adjusted using a percentage of the raw width and height as determined by the margin
-    fn find_extent(&self, points: &Vec<Vec3>) -> (Num, Num, Num, Num) {
-        let (mut x_min, mut y_min) = (Num::MAX, Num::MAX);
-        let (mut x_max, mut y_max) = (Num::MIN, Num::MIN);
-
-        for point in points {
-            x_min = x_min.min(point.x);
-            x_max = x_max.max(point.x);
-            y_min = y_min.min(point.y);
-            y_max = y_max.max(point.y);
+    pub fn scribe(&self, points: &[Vec3]) -> svg::Document {
+        SvgExporter::new(self.config.clone()).document(&self.plan(points))
+    }
+
+    /// Scribe the points straight into an SVG written to `out`, a chunk of points at a time,
+    /// without keeping the primitives, for jobs too large to hold in memory. Returns the sink.
+    ///
+    /// The `intensity` and `grouping` of the config need every primitive at once and are
+    /// silently ignored: every primitive is written ungrouped at full intensity.
+    ///
+    /// Only memory stays bounded, not time: laying the model out scribes the points as many
+    /// times as `plan` does before they are scribed for the output.
+    pub fn scribe_to<W: Write>(&self, points: &[Vec3], out: W) -> io::Result<W> {
+        let exporter = SvgExporter::new(self.config.clone());
+        self.scribe_to_with(points, &exporter, None, |_| {}, out)
+    }
+
+    /// Like `scribe_to`, written by `exporter`, which should have the same config as the
+    /// scriber, with the provenance written into the output, and `each` applied to
+    /// the plan holding a chunk of primitives before they are written. `each` can change the
+    /// primitives one by one, like `BackSide::mirror` and `ScribePlan::flatten` do, but only
+    /// sees the chunk, not the whole plan
+    pub fn scribe_to_with<W: Write>(
+        &self,
+        points: &[Vec3],
+        exporter: &SvgExporter,
+        provenance: Option<Provenance>,
+        mut each: impl FnMut(&mut ScribePlan),
+        out: W,
+    ) -> io::Result<W> {
+        let canvas_size = (self.canvas_size.0 as Num, self.canvas_size.1 as Num);
+        let mut plan = ScribePlan::new(self.view_box(points), canvas_size);
+        plan.provenance = provenance;
+        let mut stream = exporter.stream(&plan, out)?;
+        for chunk in points.chunks(CHUNK_POINTS) {
+            plan.primitives.clear();
+            self.point_scribing_strategy.scribe_points(chunk, &mut plan);
+            each(&mut plan);
+            for primitive in &plan.primitives {
+                stream.write(primitive)?;
+            }
+        }
+        stream.finish()
+    }
+
+    /// Scribe the points into a format-neutral plan
+    pub fn plan(&self, points: &[Vec3]) -> ScribePlan {
+        let canvas_size = (self.canvas_size.0 as Num, self.canvas_size.1 as Num);
+        let mut plan = ScribePlan::new(self.view_box(points), canvas_size);
+
+        // Scribe the points into the plan we just made
+        self.point_scribing_strategy
+            .scribe_points(points, &mut plan);
+        self.finish(&mut plan);
+        plan
+    }
+
+    /// Scribe the points of several source objects into one plan, grouping the primitives of
+    /// each by its name
+    pub fn plan_groups(&self, groups: &[(String, Vec<Vec3>)]) -> ScribePlan {
+        let points: Vec<Vec3> = groups
+            .iter()
+            .flat_map(|(_, points)| points.iter().copied())
+            .collect();
+        let canvas_size = (self.canvas_size.0 as Num, self.canvas_size.1 as Num);
+        let mut plan = ScribePlan::new(self.view_box(&points), canvas_size);
+
+        for (name, points) in groups {
+            let first = plan.primitives.len();
+            self.point_scribing_strategy
+                .scribe_points(points, &mut plan);
+            for primitive in &mut plan.primitives[first..] {
+                primitive.group = Some(name.clone());
+            }
+        }
+        self.finish(&mut plan);
+        plan
+    }
+
+    /// Apply the settings that work on the scribed primitives
+    fn finish(&self, plan: &mut ScribePlan) {
+        self.config.intensity.apply(plan);
+        if let Some(grouping) = &self.config.grouping {
+            grouping.apply(plan);
         }
+    }
+
+    /// The viewbox covering exactly the canvas, so that one viewbox unit is the same number
+    /// of mm along both axes. The extent of the scribed geometry is scaled by the fit mode and
+    /// placed inside the margins by the alignment
+    fn view_box(&self, points: &[Vec3]) -> Rect {
+        let canvas = Vec2::new(self.canvas_size.0 as Num, self.canvas_size.1 as Num);
+        let space = canvas - 2.0 * self.config.margin;
+        let (extent, scale) = self.find_extent(points, space);
+
+        let (align_x, align_y) = self.config.align;
+        let used = Vec2::new(extent.width, extent.height) * scale;
+        let offset = Vec2::new(
+            align_x.offset(space.x, used.x),
+            align_y.offset(space.y, used.y),
+        ) + self.config.margin;
+
+        let origin = extent.min() - offset / scale;
+        let size = canvas / scale;
+        Rect::new(origin.x, origin.y, size.x, size.y)
+    }
+
+    /// mm per model unit for an extent fit into a space of mm
+    fn scale(&self, extent: &Rect, space: Vec2) -> Num {
+        // Extents without a size along an axis can only be fit along the other one
+        let ratios = [space.x / extent.width, space.y / extent.height];
+        let ratios = ratios
+            .into_iter()
+            .filter(|ratio| ratio.is_finite() && *ratio > 0.0);
+        let scale = match self.config.fit {
+            Fit::Contain => ratios.reduce(Num::min),
+            Fit::Cover => ratios.reduce(Num::max),
+            Fit::Fixed { mm_per_unit } => Some(mm_per_unit),
+        };
+        scale.unwrap_or(1.0)
+    }
+
+    /// The extent of the scribed geometry plus the margin percentage, and the scale it is fit
+    /// into `space` at.
+    ///
+    /// Radii are worked out in mm, so in model units they can depend on the scale, which
+    /// depends on the extent. Starting from the extent of the points alone, the two are
+    /// refined until the scale settles.
+    ///
+    /// Every round scribes all the points to find their bounds. A fixed scale settles in one
+    /// round, fitting the canvas usually takes two or three and at most
+    /// `MAX_LAYOUT_ROUNDS`, which makes laying out a model cost as much as scribing it a few
+    /// times over (`cargo bench --bench layout`).
+    fn find_extent(&self, points: &[Vec3], space: Vec2) -> (Rect, Num) {
+        let points_extent = Rect::from_points(points.iter().map(|point| point.truncate()))
+            .unwrap_or(Rect::new(0.0, 0.0, 0.0, 0.0));
+        let mut extent = self.with_margin(points_extent);
+        let mut scale = self.scale(&extent, space);
+        for _ in 0..MAX_LAYOUT_ROUNDS {
+            let Some(bounds) = self.point_scribing_strategy.bounds(points, scale) else {
+                break;
+            };
+            extent = self.with_margin(bounds);
+            let previous = scale;
+            scale = self.scale(&extent, space);
+            if (scale - previous).abs() <= 1e-4 * previous {
+                break;
+            }
+        }
+        (extent, scale)
+    }
 
-        // The extact width and height: distance between the largest and smallest points
-        let (raw_width, raw_height) = (x_max - x_min, y_max - y_min);
+    /// The extent grown by the margin percentage of its width and height on every side
+    fn with_margin(&self, extent: Rect) -> Rect {
+        let margin = self.config.margin_percentage;
+        extent.expand(Vec2::new(extent.width, extent.height) * margin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(config: ScriberConfig) -> ScribePlan {
+        // A 2x1 model on a 100x100mm canvas
+        let points = [Vec3::new(0.0, 0.0, 0.0), Vec3::new(2.0, 1.0, 0.0)];
+        let config = ScriberConfig {
+            margin_percentage: 0.0,
+            ..config
+        };
+        Scriber::with_config(CircleScriber::new(), (100, 100), config).plan(&points)
+    }
 
-        // The margins we want to extend the max and min values by - calculated as a percentage of the raw width and height
-        let (width_margin, height_margin) = (self.margin_percentage * raw_width, self.margin_percentage * raw_height);
+    #[test]
+    fn test_fit_modes() {
+        let plan = layout(ScriberConfig {
+            margin: 10.0,
+            ..Default::default()
+        });
+        assert_eq!(plan.mm_per_unit(), 40.0);
+        assert_eq!(plan.to_mm(Vec2::new(0.0, 0.0)), Vec2::new(10.0, 30.0));
+
+        let plan = layout(ScriberConfig {
+            fit: Fit::Cover,
+            align: (Align::Start, Align::End),
+            ..Default::default()
+        });
+        assert_eq!(plan.mm_per_unit(), 100.0);
+        assert_eq!(plan.to_mm(Vec2::new(0.0, 1.0)), Vec2::new(0.0, 100.0));
 
-        // Extend the min and max values
-        x_min -= width_margin;
-        y_min -= height_margin;
-        x_max += width_margin;
-        y_max += height_margin;
+        let plan = layout(ScriberConfig {
+            fit: Fit::Fixed { mm_per_unit: 10.0 },
+            align: (Align::End, Align::Start),
+            ..Default::default()
+        });
+        assert_eq!(plan.mm_per_unit(), 10.0);
+        assert_eq!(plan.to_mm(Vec2::new(2.0, 0.0)), Vec2::new(100.0, 0.0));
+    }
 
-        // Return the min values and the new distances for width and height
-        (x_min, y_min, (x_max - x_min), (y_max - y_min))
+    #[test]
+    fn test_extent_includes_radii() {
+        // Circles of radius 1 around points 2 units apart fill a 4x2 area
+        let points = [Vec3::new(0.0, 0.0, 4.0), Vec3::new(2.0, 0.0, 4.0)];
+        let config = ScriberConfig {
+            margin_percentage: 0.0,
+            ..Default::default()
+        };
+        let scriber = Scriber::with_config(CircleScriber::new(), (80, 80), config);
+        let plan = scriber.plan(&points);
+        assert_eq!(plan.mm_per_unit(), 20.0);
+        assert_eq!(plan.to_mm(Vec2::new(-1.0, -1.0)), Vec2::new(0.0, 20.0));
     }
 }
@@ -0,0 +1,198 @@
+use glam::{Vec2, Vec3};
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_3, PI, TAU};
+
+use super::arc::{glint_direction, image_span};
+use super::{
+    circle_layer, CircleScriber, HoloPointStrategy, ImageKind, Num, ScribePlan, Shape, ViewingCone,
+    ViewingGeometry,
+};
+
+/// Point on the plate at `offset` from `center`, where offsets use y towards the top of the
+/// plate, which is -y in the plan
+fn plate_point(center: Vec3, offset: Vec2) -> Vec2 {
+    Vec2::new(center.x + offset.x, center.y - offset.y)
+}
+
+/// Scribes elliptical arcs for plates looked at from an angle.
+///
+/// Seen from `viewer_angle` off the plate normal, the plate is foreshortened vertically by
+/// `k = cos(viewer_angle)`, so the scratches are ellipses with the same ratio between their
+/// vertical and horizontal axes. The axes are `r k` and `r k^2`, which keeps the radius of
+/// curvature at the top and bottom of the ellipse equal to the circle radius `r`: the glint
+/// for the nominal viewer moves exactly as it would on the circle, so the apparent depth is
+/// unchanged. Like `ArcScriber`, only the part glinting for viewers inside the viewing cone
+/// is scribed, which depends on both the light and the viewer angle.
+pub struct EllipseScriber {
+    circles: CircleScriber,
+    geometry: ViewingGeometry,
+    cone: ViewingCone,
+}
+
+impl EllipseScriber {
+    pub fn new(circles: CircleScriber, geometry: ViewingGeometry, cone: ViewingCone) -> Self {
+        Self {
+            circles,
+            geometry,
+            cone,
+        }
+    }
+
+    /// Horizontal and vertical semi-axis of the ellipse replacing a circle of `radius`
+    pub fn semi_axes(&self, radius: Num) -> (Num, Num) {
+        let k = self.geometry.viewer_angle.to_radians().cos().max(0.1);
+        (radius * k, radius * k * k)
+    }
+}
+
+impl HoloPointStrategy for EllipseScriber {
+    /// This is a scriber that draws elliptical arcs, in the same layers as the circles of `CircleScriber`.
+    fn scribe_points(&self, points: &[Vec3], plan: &mut ScribePlan) {
+        let scale = plan.mm_per_unit();
+        for &point in points {
+            let (radius, image) = self.circles.circle(point, scale);
+            let (a, b) = self.semi_axes(radius);
+            let (start, sweep) = match image_span(&self.geometry, &self.cone, image) {
+                Some((start, end)) => {
+                    // The glint directions are normals of the ellipse, which sit at these
+                    // values of the ellipse parameter
+                    let parameter = |normal: Num| (b * normal.sin()).atan2(a * normal.cos());
+                    let (t0, mut t1) = (parameter(start), parameter(end));
+                    if t1 < t0 {
+                        t1 += TAU;
+                    }
+                    // The parameter runs the other way in the plan, where y points down
+                    (-t1, t1 - t0)
+                }
+                None => (0.0, TAU),
+            };
+            let arc = Shape::EllipticalArc {
+                center: point.truncate(),
+                radii: Vec2::new(a, b),
+                start,
+                sweep,
+            };
+            plan.push(
+                arc,
+                self.circles.depth(point, scale),
+                image,
+                circle_layer(image),
+            );
+        }
+    }
+}
+
+/// Scribes parabolic scratches for overhead and oblique lighting.
+///
+/// Each parabola has its vertex where the circle would glint for the nominal viewer, its axis
+/// along the direction the light and viewer angles put that glint in, and a radius of
+/// curvature at the vertex equal to the circle radius, so the apparent depth there matches
+/// `CircleScriber`. Away from the vertex a parabola flattens out, so viewers far off axis see
+/// the glint travel further than on a circle. The arms cover the viewing cone, up to 60
+/// degrees either side of the vertex.
+pub struct ParabolaScriber {
+    circles: CircleScriber,
+    geometry: ViewingGeometry,
+    cone: ViewingCone,
+}
+
+impl ParabolaScriber {
+    pub fn new(circles: CircleScriber, geometry: ViewingGeometry, cone: ViewingCone) -> Self {
+        Self {
+            circles,
+            geometry,
+            cone,
+        }
+    }
+
+    /// Direction of the parabola axis, and the range of normal directions the arms cover
+    /// relative to it
+    fn axis(&self, image: ImageKind) -> (Num, Num, Num) {
+        let flip = match image {
+            ImageKind::Virtual => 0.0,
+            ImageKind::Real => PI,
+        };
+        match (
+            glint_direction(&self.geometry, 0.0, 0.0),
+            image_span(&self.geometry, &self.cone, image),
+        ) {
+            (Some(axis), Some((start, end))) => {
+                let axis = axis + flip;
+                (
+                    axis,
+                    (start - axis).max(-FRAC_PI_3),
+                    (end - axis).min(FRAC_PI_3),
+                )
+            }
+            _ => (FRAC_PI_2 + flip, -FRAC_PI_3, FRAC_PI_3),
+        }
+    }
+}
+
+impl HoloPointStrategy for ParabolaScriber {
+    /// This is a scriber that draws each parabola as a single quadratic Bézier curve, which represents a parabola
+    /// exactly. Parabolas are drawn into the same layers as the circles of `CircleScriber`.
+    fn scribe_points(&self, points: &[Vec3], plan: &mut ScribePlan) {
+        let scale = plan.mm_per_unit();
+        for &point in points {
+            let (radius, image) = self.circles.circle(point, scale);
+            if radius == 0.0 {
+                // Points on the plate have no curvature to scribe, and a dot would still be
+                // plunged by machines while showing nothing
+                continue;
+            }
+            let (axis, min_offset, max_offset) = self.axis(image);
+            let u = Vec2::new(axis.cos(), axis.sin());
+            let w = u.perp();
+            // P(s) = r u + s w - s^2 / 2r u, whose normal is turned by atan(s / r) from u
+            let curve = |s: Num| radius * u + s * w - (s * s / (2.0 * radius)) * u;
+            let tangent = |s: Num| w - (s / radius) * u;
+            let (s0, s1) = (radius * min_offset.tan(), radius * max_offset.tan());
+            let control = curve(s0) + 0.5 * (s1 - s0) * tangent(s0);
+            let parabola = Shape::Quadratic {
+                start: plate_point(point, curve(s0)),
+                control: plate_point(point, control),
+                end: plate_point(point, curve(s1)),
+            };
+            plan.push(
+                parabola,
+                self.circles.depth(point, scale),
+                image,
+                circle_layer(image),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scriber::Rect;
+
+    #[test]
+    fn test_parabola_skips_points_on_the_plate() {
+        let scriber = ParabolaScriber::new(
+            CircleScriber::new(),
+            ViewingGeometry::default(),
+            ViewingCone::default(),
+        );
+        let mut plan = ScribePlan::new(Rect::new(0.0, 0.0, 10.0, 10.0), (10.0, 10.0));
+        let points = [Vec3::new(5.0, 5.0, 0.0), Vec3::new(5.0, 5.0, -2.0)];
+        scriber.scribe_points(&points, &mut plan);
+        assert_eq!(plan.primitives.len(), 1);
+        let bounds = plan.primitives[0].shape.bounds();
+        assert!(bounds.min().is_finite() && bounds.max().is_finite());
+    }
+
+    #[test]
+    fn test_ellipse_keeps_vertex_curvature() {
+        let geometry = ViewingGeometry {
+            viewer_angle: 30.0,
+            ..Default::default()
+        };
+        let scriber = EllipseScriber::new(CircleScriber::new(), geometry, ViewingCone::default());
+        let (a, b) = scriber.semi_axes(2.0);
+        assert!((b / a - 30f32.to_radians().cos()).abs() < 1e-6);
+        // radius of curvature at the top of the ellipse
+        assert!((a * a / b - 2.0).abs() < 1e-5);
+    }
+}
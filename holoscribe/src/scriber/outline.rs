@@ -0,0 +1,560 @@
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::f32::consts::TAU;
+use std::fs;
+use svg::node::element::path::{Command, Data, Position};
+use svg::parser::Event;
+
+use super::plan::{arc_points, MAX_STEPS};
+use super::{Num, ScribePlan, Shape};
+
+/// Area in mm² under which a polygon is taken to have none, like a curved outline whose
+/// curves were lost
+const MIN_AREA: Num = 0.01;
+
+/// A closed shape on the canvas, in mm from its top left corner with y pointing down
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "shape", rename_all = "snake_case")]
+pub enum Outline {
+    Rectangle {
+        x: Num,
+        y: Num,
+        width: Num,
+        height: Num,
+        #[serde(default)]
+        corner_radius: Num,
+    },
+    Circle {
+        center: Vec2,
+        radius: Num,
+    },
+    Polygon {
+        points: Vec<Vec2>,
+    },
+}
+
+/// A line or circle some edge of an outline lies on. Crossing one doesn't have to mean
+/// crossing the outline, but crossing the outline always means crossing one of them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Boundary {
+    Line(Vec2, Vec2),
+    Circle(Vec2, Num),
+}
+
+impl Outline {
+    /// The whole canvas of a plan
+    pub fn canvas(plan: &ScribePlan) -> Self {
+        Outline::Rectangle {
+            x: 0.0,
+            y: 0.0,
+            width: plan.canvas_size.0,
+            height: plan.canvas_size.1,
+            corner_radius: 0.0,
+        }
+    }
+
+    /// The largest circle in the middle of the canvas of a plan
+    pub fn inscribed_circle(plan: &ScribePlan) -> Self {
+        let (width, height) = plan.canvas_size;
+        Outline::Circle {
+            center: Vec2::new(width, height) / 2.0,
+            radius: width.min(height) / 2.0,
+        }
+    }
+
+    /// The first polygon, polyline or path of an SVG file, taking its user units as mm.
+    /// Curves in paths are flattened into line segments deviating at most `tolerance` mm
+    /// from them. Fails if there is none, or if it has no area
+    pub fn polygon_from_svg(file_path: &str, tolerance: Num) -> Result<Self, Box<dyn Error>> {
+        let mut content = String::new();
+        for event in svg::open(file_path, &mut content)? {
+            let Event::Tag(tag, _, attributes) = event else {
+                continue;
+            };
+            let points = match tag {
+                "polygon" | "polyline" => attributes
+                    .get("points")
+                    .map(|points| parse_points(points))
+                    .transpose()?,
+                "path" => attributes
+                    .get("d")
+                    .map(|data| path_points(&Data::parse(data)?, tolerance))
+                    .transpose()?,
+                _ => None,
+            };
+            if let Some(points) = points.filter(|points| points.len() >= 3) {
+                return polygon(points);
+            }
+        }
+        Err("no polygon in the SVG file".into())
+    }
+
+    /// The first `LWPOLYLINE` or `POLYLINE` of a DXF file in mm, measured from the bottom left
+    /// corner of a canvas `canvas_height` mm high as `DxfExporter` writes them. Fails if
+    /// there is none, or if it has no area
+    pub fn polygon_from_dxf(file_path: &str, canvas_height: Num) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(file_path)?;
+        let lines: Vec<&str> = content.lines().map(str::trim).collect();
+        // The polyline being read, and whether it is a POLYLINE, which has a dummy point of
+        // its own before the vertices
+        let mut polyline: Option<(bool, Vec<Vec2>)> = None;
+        let mut x = None;
+        for pair in lines.chunks_exact(2) {
+            let (code, value) = (pair[0], pair[1]);
+            match (code, value) {
+                ("0", "VERTEX") => {}
+                ("0", _) => {
+                    if let Some((header, mut points)) = polyline.take() {
+                        if header && !points.is_empty() {
+                            points.remove(0);
+                        }
+                        if points.len() >= 3 {
+                            return polygon(points);
+                        }
+                    }
+                    if let "LWPOLYLINE" | "POLYLINE" = value {
+                        polyline = Some((value == "POLYLINE", Vec::new()));
+                    }
+                }
+                ("10", _) if polyline.is_some() => x = Some(value.parse::<Num>()?),
+                ("20", _) => {
+                    if let (Some((_, points)), Some(x)) = (&mut polyline, x.take()) {
+                        points.push(Vec2::new(x, canvas_height - value.parse::<Num>()?));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Err("no polygon in the DXF file".into())
+    }
+
+    /// The outline in the viewbox units of a plan
+    pub(crate) fn to_units(&self, plan: &ScribePlan) -> Outline {
+        let scale = plan.mm_per_unit();
+        let at = |point: Vec2| plan.from_mm(point);
+        match self {
+            Outline::Rectangle {
+                x,
+                y,
+                width,
+                height,
+                corner_radius,
+            } => {
+                let corner = at(Vec2::new(*x, *y));
+                Outline::Rectangle {
+                    x: corner.x,
+                    y: corner.y,
+                    width: width / scale,
+                    height: height / scale,
+                    corner_radius: corner_radius / scale,
+                }
+            }
+            Outline::Circle { center, radius } => Outline::Circle {
+                center: at(*center),
+                radius: radius / scale,
+            },
+            Outline::Polygon { points } => Outline::Polygon {
+                points: points.iter().map(|&point| at(point)).collect(),
+            },
+        }
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        match self {
+            Outline::Rectangle {
+                x,
+                y,
+                width,
+                height,
+                corner_radius,
+            } => {
+                let half = Vec2::new(*width, *height) / 2.0;
+                let radius = corner_radius.min(half.min_element()).max(0.0);
+                // Distance from the rectangle shrunk by the corner radius
+                let offset = (point - Vec2::new(*x, *y) - half).abs() - (half - radius);
+                offset.max(Vec2::ZERO).length() <= radius && offset.max_element() <= radius
+            }
+            Outline::Circle { center, radius } => point.distance(*center) <= *radius,
+            Outline::Polygon { points } => {
+                // Even-odd rule, counting the edges crossed by a ray towards +x
+                let mut inside = false;
+                for (i, &a) in points.iter().enumerate() {
+                    let b = points[(i + 1) % points.len()];
+                    if (a.y > point.y) != (b.y > point.y) {
+                        let x = a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x);
+                        if point.x < x {
+                            inside = !inside;
+                        }
+                    }
+                }
+                inside
+            }
+        }
+    }
+
+    pub(crate) fn boundaries(&self) -> Vec<Boundary> {
+        match self {
+            Outline::Rectangle {
+                x,
+                y,
+                width,
+                height,
+                corner_radius,
+            } => {
+                let (min, max) = (Vec2::new(*x, *y), Vec2::new(x + width, y + height));
+                let corners = [min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)];
+                let mut boundaries: Vec<Boundary> = (0..4)
+                    .map(|i| Boundary::Line(corners[i], corners[(i + 1) % 4]))
+                    .collect();
+                let radius = corner_radius.min(width.min(*height) / 2.0);
+                if radius > 0.0 {
+                    let inset = [
+                        Vec2::new(radius, radius),
+                        Vec2::new(-radius, radius),
+                        Vec2::new(-radius, -radius),
+                        Vec2::new(radius, -radius),
+                    ];
+                    boundaries
+                        .extend((0..4).map(|i| Boundary::Circle(corners[i] + inset[i], radius)));
+                }
+                boundaries
+            }
+            Outline::Circle { center, radius } => vec![Boundary::Circle(*center, *radius)],
+            Outline::Polygon { points } => (0..points.len())
+                .map(|i| Boundary::Line(points[i], points[(i + 1) % points.len()]))
+                .collect(),
+        }
+    }
+}
+
+impl Boundary {
+    /// Angles on the circle around `center` with `radius` where it crosses the boundary
+    pub(crate) fn circle_crossings(&self, center: Vec2, radius: Num) -> Vec<Num> {
+        let points = match *self {
+            Boundary::Line(a, b) => {
+                let direction = (b - a).normalize_or_zero();
+                // Closest point of the line to the center, and half the chord through it
+                let foot = a + direction * (center - a).dot(direction);
+                let distance = foot.distance(center);
+                if distance >= radius || direction == Vec2::ZERO {
+                    return Vec::new();
+                }
+                let half = (radius * radius - distance * distance).sqrt();
+                vec![foot - direction * half, foot + direction * half]
+            }
+            Boundary::Circle(other, other_radius) => {
+                let d = center.distance(other);
+                if d >= radius + other_radius || d <= (radius - other_radius).abs() || d == 0.0 {
+                    return Vec::new();
+                }
+                let along = (d * d + radius * radius - other_radius * other_radius) / (2.0 * d);
+                let half = (radius * radius - along * along).max(0.0).sqrt();
+                let axis = (other - center) / d;
+                let middle = center + axis * along;
+                vec![middle + axis.perp() * half, middle - axis.perp() * half]
+            }
+        };
+        points
+            .into_iter()
+            .map(|point| {
+                let offset = point - center;
+                offset.y.atan2(offset.x)
+            })
+            .collect()
+    }
+
+    /// Fractions of the way from `a` to `b` where the segment crosses the boundary
+    pub(crate) fn segment_crossings(&self, a: Vec2, b: Vec2) -> Vec<Num> {
+        let direction = b - a;
+        match *self {
+            Boundary::Line(p, q) => {
+                let edge = q - p;
+                let denominator = direction.perp_dot(edge);
+                if denominator == 0.0 {
+                    return Vec::new();
+                }
+                vec![(p - a).perp_dot(edge) / denominator]
+            }
+            Boundary::Circle(center, radius) => {
+                // |a + t direction - center| = radius
+                let offset = a - center;
+                let (qa, qb) = (direction.length_squared(), 2.0 * offset.dot(direction));
+                let qc = offset.length_squared() - radius * radius;
+                let discriminant = qb * qb - 4.0 * qa * qc;
+                if qa == 0.0 || discriminant <= 0.0 {
+                    return Vec::new();
+                }
+                let root = discriminant.sqrt();
+                vec![(-qb - root) / (2.0 * qa), (-qb + root) / (2.0 * qa)]
+            }
+        }
+    }
+}
+
+/// Points of an SVG `points` attribute
+fn parse_points(points: &str) -> Result<Vec<Vec2>, Box<dyn Error>> {
+    let numbers = points
+        .split([' ', ',', '\n', '\t'])
+        .filter(|number| !number.is_empty())
+        .map(|number| number.parse::<Num>())
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(numbers
+        .chunks_exact(2)
+        .map(|pair| Vec2::new(pair[0], pair[1]))
+        .collect())
+}
+
+/// A polygon outline, unless its area is too small for anything to be scribed inside it
+fn polygon(points: Vec<Vec2>) -> Result<Outline, Box<dyn Error>> {
+    // Shoelace formula
+    let area = (0..points.len())
+        .map(|i| points[i].perp_dot(points[(i + 1) % points.len()]))
+        .sum::<Num>()
+        .abs()
+        / 2.0;
+    if area.is_nan() || area < MIN_AREA {
+        return Err(format!("the polygon has an area of {area} mm², nothing fits inside").into());
+    }
+    Ok(Outline::Polygon { points })
+}
+
+/// The points a path moves through, ending at its first close. Curves are flattened into
+/// line segments deviating at most `tolerance` from them
+fn path_points(data: &Data, tolerance: Num) -> Result<Vec<Vec2>, Box<dyn Error>> {
+    let mut points = Vec::new();
+    let mut current = Vec2::ZERO;
+    // The last control point of the segment before, if it was a cubic or a quadratic curve,
+    // which smooth curves mirror for their first one
+    let (mut cubic_control, mut quadratic_control) = (None, None);
+    for command in data.iter() {
+        let (position, parameters, step) = match command {
+            Command::Close => break,
+            Command::HorizontalLine(position, parameters) => {
+                for &x in parameters.iter() {
+                    current = match position {
+                        Position::Absolute => Vec2::new(x, current.y),
+                        Position::Relative => current + Vec2::new(x, 0.0),
+                    };
+                    points.push(current);
+                }
+                (cubic_control, quadratic_control) = (None, None);
+                continue;
+            }
+            Command::VerticalLine(position, parameters) => {
+                for &y in parameters.iter() {
+                    current = match position {
+                        Position::Absolute => Vec2::new(current.x, y),
+                        Position::Relative => current + Vec2::new(0.0, y),
+                    };
+                    points.push(current);
+                }
+                (cubic_control, quadratic_control) = (None, None);
+                continue;
+            }
+            Command::Move(position, parameters)
+            | Command::Line(position, parameters)
+            | Command::SmoothQuadraticCurve(position, parameters) => (position, parameters, 2),
+            Command::QuadraticCurve(position, parameters)
+            | Command::SmoothCubicCurve(position, parameters) => (position, parameters, 4),
+            Command::CubicCurve(position, parameters) => (position, parameters, 6),
+            Command::EllipticalArc(position, parameters) => (position, parameters, 7),
+        };
+        for segment in parameters.chunks_exact(step) {
+            // Relative coordinates are measured from the start of the segment
+            let origin = match position {
+                Position::Absolute => Vec2::ZERO,
+                Position::Relative => current,
+            };
+            let at = |i: usize| origin + Vec2::new(segment[i], segment[i + 1]);
+            let start = current;
+            let mirrored = |control: Option<Vec2>| 2.0 * start - control.unwrap_or(start);
+            let (mut cubic, mut quadratic) = (None, None);
+            current = match command {
+                Command::CubicCurve(..) | Command::SmoothCubicCurve(..) => {
+                    let (first, second, end) = match command {
+                        Command::CubicCurve(..) => (at(0), at(2), at(4)),
+                        _ => (mirrored(cubic_control), at(0), at(2)),
+                    };
+                    points.extend(cubic_points([start, first, second, end], tolerance));
+                    cubic = Some(second);
+                    end
+                }
+                Command::QuadraticCurve(..) | Command::SmoothQuadraticCurve(..) => {
+                    let (control, end) = match command {
+                        Command::QuadraticCurve(..) => (at(0), at(2)),
+                        _ => (mirrored(quadratic_control), at(0)),
+                    };
+                    let curve = Shape::Quadratic {
+                        start,
+                        control,
+                        end,
+                    };
+                    points.extend(curve.to_polyline(tolerance).into_iter().skip(1));
+                    quadratic = Some(control);
+                    end
+                }
+                Command::EllipticalArc(..) => {
+                    let end = at(5);
+                    let radii = Vec2::new(segment[0], segment[1]);
+                    let flags = (segment[3] != 0.0, segment[4] != 0.0);
+                    let rotation = segment[2].to_radians();
+                    points.extend(svg_arc_points(
+                        start, end, radii, rotation, flags, tolerance,
+                    ));
+                    end
+                }
+                _ => {
+                    points.push(at(0));
+                    at(0)
+                }
+            };
+            (cubic_control, quadratic_control) = (cubic, quadratic);
+        }
+    }
+    Ok(points)
+}
+
+/// Points along a cubic Bézier curve after its start, with the segments between them
+/// deviating at most `tolerance` from it
+fn cubic_points([p0, p1, p2, p3]: [Vec2; 4], tolerance: Num) -> Vec<Vec2> {
+    // The chord error of a segment spanning h of the curve parameter is at most |B''| h^2 / 8,
+    // and |B''| is at most 6 times the larger second difference of the control points
+    let bend = (p0 - 2.0 * p1 + p2)
+        .length()
+        .max((p1 - 2.0 * p2 + p3).length());
+    let steps = ((0.75 * bend / tolerance.max(0.0)).sqrt().ceil() as usize).clamp(1, MAX_STEPS);
+    (1..=steps)
+        .map(|step| {
+            let t = step as Num / steps as Num;
+            let u = 1.0 - t;
+            p0 * u * u * u + 3.0 * p1 * u * u * t + 3.0 * p2 * u * t * t + p3 * t * t * t
+        })
+        .collect()
+}
+
+/// Points along an SVG elliptical arc command after its start, with the segments between
+/// them deviating at most `tolerance` from it. `rotation` is the angle of the x axis of the
+/// ellipse in radians, `flags` the large arc and sweep flags
+fn svg_arc_points(
+    start: Vec2,
+    end: Vec2,
+    radii: Vec2,
+    rotation: Num,
+    (large_arc, sweep): (bool, bool),
+    tolerance: Num,
+) -> Vec<Vec2> {
+    let mut radii = radii.abs();
+    if start == end {
+        return Vec::new();
+    }
+    if radii.x == 0.0 || radii.y == 0.0 {
+        return vec![end];
+    }
+    // The center parameterization of the SVG specification, appendix B.2.4, in the frame of
+    // the ellipse axes
+    let axis = Vec2::from_angle(rotation);
+    let half = Vec2::from_angle(-rotation).rotate((start - end) / 2.0);
+    // Radii too small to reach from start to end are scaled up until they just do
+    let reach = (half / radii).length_squared();
+    if reach > 1.0 {
+        radii *= reach.sqrt();
+    }
+    let (r2, h2) = (radii * radii, half * half);
+    let numerator = r2.x * r2.y - r2.x * h2.y - r2.y * h2.x;
+    let denominator = r2.x * h2.y + r2.y * h2.x;
+    let sign = if large_arc == sweep { -1.0 } else { 1.0 };
+    let factor = sign * (numerator / denominator).max(0.0).sqrt();
+    let center = factor * Vec2::new(radii.x * half.y / radii.y, -radii.y * half.x / radii.x);
+
+    let angle = |from: Vec2, to: Vec2| from.perp_dot(to).atan2(from.dot(to));
+    let first = (half - center) / radii;
+    let theta = angle(Vec2::X, first);
+    let mut delta = angle(first, (-half - center) / radii);
+    if sweep && delta < 0.0 {
+        delta += TAU;
+    } else if !sweep && delta > 0.0 {
+        delta -= TAU;
+    }
+
+    let center = axis.rotate(center) + (start + end) / 2.0;
+    let mut points: Vec<Vec2> = arc_points(Vec2::ZERO, radii, theta, delta, tolerance)
+        .into_iter()
+        .skip(1)
+        .map(|point| center + axis.rotate(point))
+        .collect();
+    if let Some(last) = points.last_mut() {
+        *last = end;
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contains() {
+        let rounded = Outline::Rectangle {
+            x: 0.0,
+            y: 0.0,
+            width: 10.0,
+            height: 10.0,
+            corner_radius: 2.0,
+        };
+        assert!(rounded.contains(Vec2::new(5.0, 0.5)));
+        assert!(rounded.contains(Vec2::new(1.0, 1.0)));
+        assert!(!rounded.contains(Vec2::new(0.2, 0.2)));
+        assert!(!rounded.contains(Vec2::new(11.0, 5.0)));
+
+        let triangle = Outline::Polygon {
+            points: vec![Vec2::ZERO, Vec2::new(4.0, 0.0), Vec2::new(0.0, 4.0)],
+        };
+        assert!(triangle.contains(Vec2::new(1.0, 1.0)));
+        assert!(!triangle.contains(Vec2::new(3.0, 3.0)));
+    }
+
+    #[test]
+    fn test_path_points() {
+        let data = Data::parse("M 0 0 h 10 V 10 l -10 0 z").unwrap();
+        assert_eq!(
+            path_points(&data, 0.01).unwrap(),
+            [
+                Vec2::ZERO,
+                Vec2::new(10.0, 0.0),
+                Vec2::new(10.0, 10.0),
+                Vec2::new(0.0, 10.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_curved_path_points() {
+        // A circle of radius 50 around (50, 50) drawn as two arcs, the second one relative
+        let data = Data::parse("M 0 50 A 50 50 0 0 1 100 50 a 50 50 0 0 1 -100 0 Z").unwrap();
+        let points = path_points(&data, 0.01).unwrap();
+        assert!(points.len() > 20);
+        assert_eq!(points[points.len() / 2], Vec2::new(100.0, 50.0));
+        assert!(points
+            .iter()
+            .all(|point| (point.distance(Vec2::splat(50.0)) - 50.0).abs() < 1e-3));
+        let outline = polygon(points).unwrap();
+        assert!(outline.contains(Vec2::new(50.0, 50.0)));
+        assert!(!outline.contains(Vec2::new(5.0, 5.0)));
+
+        // Quadratic and cubic curves, with the smooth ones mirroring the control point before
+        let data = Data::parse("M 0 0 Q 5 10 10 0 T 20 0 C 20 -5 30 -5 30 0 S 40 5 40 0").unwrap();
+        let points = path_points(&data, 0.01).unwrap();
+        assert!(points.contains(&Vec2::new(10.0, 0.0)));
+        assert_eq!(points.last(), Some(&Vec2::new(40.0, 0.0)));
+        let lowest = points.iter().map(|point| point.y).fold(0.0, Num::min);
+        let highest = points.iter().map(|point| point.y).fold(0.0, Num::max);
+        assert!((lowest + 5.0).abs() < 0.02 && (highest - 5.0).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_polygon_without_area() {
+        let line = vec![Vec2::ZERO, Vec2::new(50.0, 0.0), Vec2::new(100.0, 0.0)];
+        assert!(polygon(line).is_err());
+    }
+}
@@ -0,0 +1,596 @@
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use super::{Fiducial, ImageKind, MirrorAxis, Num};
+use crate::export::Provenance;
+
+/// An axis-aligned rectangle
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: Num,
+    pub y: Num,
+    pub width: Num,
+    pub height: Num,
+}
+
+impl Rect {
+    pub fn new(x: Num, y: Num, width: Num, height: Num) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn as_tuple(&self) -> (Num, Num, Num, Num) {
+        (self.x, self.y, self.width, self.height)
+    }
+
+    /// The smallest rectangle containing all points, `None` if there are none
+    pub fn from_points(points: impl IntoIterator<Item = Vec2>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        let (min, max) = points.fold((first, first), |(min, max), point| {
+            (min.min(point), max.max(point))
+        });
+        let size = max - min;
+        Some(Self::new(min.x, min.y, size.x, size.y))
+    }
+
+    pub fn min(&self) -> Vec2 {
+        Vec2::new(self.x, self.y)
+    }
+
+    pub fn max(&self) -> Vec2 {
+        Vec2::new(self.x + self.width, self.y + self.height)
+    }
+
+    /// The smallest rectangle containing both rectangles
+    pub fn union(&self, other: &Rect) -> Rect {
+        Rect::from_points([self.min(), self.max(), other.min(), other.max()])
+            .expect("there are points")
+    }
+
+    /// The rectangle grown by `margin` on every side
+    pub fn expand(&self, margin: Vec2) -> Rect {
+        Rect::from_points([self.min() - margin, self.max() + margin]).expect("there are points")
+    }
+}
+
+/// The geometry of a single scribed line.
+///
+/// Coordinates use the frame of the output document: x points right and y points down.
+/// Angles are in radians, measured from +x towards +y.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Shape {
+    Circle {
+        center: Vec2,
+        radius: Num,
+    },
+    /// The part of a circle from `start` to `start + sweep`
+    Arc {
+        center: Vec2,
+        radius: Num,
+        start: Num,
+        sweep: Num,
+    },
+    /// The part of an axis-aligned ellipse from parameter `start` to `start + sweep`, where the
+    /// ellipse is `center + (radii.x cos t, radii.y sin t)`
+    EllipticalArc {
+        center: Vec2,
+        radii: Vec2,
+        start: Num,
+        sweep: Num,
+    },
+    /// A quadratic Bézier curve, which is a segment of a parabola
+    Quadratic {
+        start: Vec2,
+        control: Vec2,
+        end: Vec2,
+    },
+    Polyline {
+        points: Vec<Vec2>,
+    },
+}
+
+impl Shape {
+    /// The smallest rectangle containing the shape
+    pub fn bounds(&self) -> Rect {
+        let points = match self {
+            Shape::Circle { center, radius } => vec![*center - *radius, *center + *radius],
+            Shape::Arc {
+                center,
+                radius,
+                start,
+                sweep,
+            } => arc_extremes(*center, Vec2::splat(*radius), *start, *sweep),
+            Shape::EllipticalArc {
+                center,
+                radii,
+                start,
+                sweep,
+            } => arc_extremes(*center, *radii, *start, *sweep),
+            Shape::Quadratic {
+                start,
+                control,
+                end,
+            } => {
+                // Along each axis the curve turns around where its derivative is zero
+                let bend = *start - 2.0 * *control + *end;
+                let t = (*start - *control) / bend;
+                let mut points = vec![*start, *end];
+                for t in [t.x, t.y] {
+                    if t > 0.0 && t < 1.0 {
+                        points.push(start.lerp(*control, t).lerp(control.lerp(*end, t), t));
+                    }
+                }
+                points
+            }
+            Shape::Polyline { points } => points.clone(),
+        };
+        Rect::from_points(points).unwrap_or(Rect::new(0.0, 0.0, 0.0, 0.0))
+    }
+
+    /// Where scribing the shape starts and ends
+    pub fn endpoints(&self) -> (Vec2, Vec2) {
+        let on_ellipse =
+            |center: Vec2, radii: Vec2, t: Num| center + radii * Vec2::new(t.cos(), t.sin());
+        match self {
+            Shape::Circle { center, radius } => {
+                let point = *center + Vec2::new(*radius, 0.0);
+                (point, point)
+            }
+            Shape::Arc {
+                center,
+                radius,
+                start,
+                sweep,
+            } => (
+                on_ellipse(*center, Vec2::splat(*radius), *start),
+                on_ellipse(*center, Vec2::splat(*radius), start + sweep),
+            ),
+            Shape::EllipticalArc {
+                center,
+                radii,
+                start,
+                sweep,
+            } => (
+                on_ellipse(*center, *radii, *start),
+                on_ellipse(*center, *radii, start + sweep),
+            ),
+            Shape::Quadratic { start, end, .. } => (*start, *end),
+            Shape::Polyline { points } => (
+                points.first().copied().unwrap_or_default(),
+                points.last().copied().unwrap_or_default(),
+            ),
+        }
+    }
+
+    /// The same line, scribed in the other direction
+    pub fn reversed(&self) -> Shape {
+        match self {
+            Shape::Circle { .. } => self.clone(),
+            Shape::Arc {
+                center,
+                radius,
+                start,
+                sweep,
+            } => Shape::Arc {
+                center: *center,
+                radius: *radius,
+                start: start + sweep,
+                sweep: -sweep,
+            },
+            Shape::EllipticalArc {
+                center,
+                radii,
+                start,
+                sweep,
+            } => Shape::EllipticalArc {
+                center: *center,
+                radii: *radii,
+                start: start + sweep,
+                sweep: -sweep,
+            },
+            Shape::Quadratic {
+                start,
+                control,
+                end,
+            } => Shape::Quadratic {
+                start: *end,
+                control: *control,
+                end: *start,
+            },
+            Shape::Polyline { points } => Shape::Polyline {
+                points: points.iter().rev().copied().collect(),
+            },
+        }
+    }
+
+    /// The shape mirrored about a vertical or horizontal line at `at`
+    pub fn mirrored(&self, axis: MirrorAxis, at: Num) -> Shape {
+        let point = |point: &Vec2| match axis {
+            MirrorAxis::Vertical => Vec2::new(2.0 * at - point.x, point.y),
+            MirrorAxis::Horizontal => Vec2::new(point.x, 2.0 * at - point.y),
+        };
+        // Mirroring turns an angle t into pi - t or -t, and reverses the sweep
+        let angle = |t: Num| match axis {
+            MirrorAxis::Vertical => PI - t,
+            MirrorAxis::Horizontal => -t,
+        };
+        match self {
+            Shape::Circle { center, radius } => Shape::Circle {
+                center: point(center),
+                radius: *radius,
+            },
+            Shape::Arc {
+                center,
+                radius,
+                start,
+                sweep,
+            } => Shape::Arc {
+                center: point(center),
+                radius: *radius,
+                start: angle(*start),
+                sweep: -sweep,
+            },
+            Shape::EllipticalArc {
+                center,
+                radii,
+                start,
+                sweep,
+            } => Shape::EllipticalArc {
+                center: point(center),
+                radii: *radii,
+                start: angle(*start),
+                sweep: -sweep,
+            },
+            Shape::Quadratic {
+                start,
+                control,
+                end,
+            } => Shape::Quadratic {
+                start: point(start),
+                control: point(control),
+                end: point(end),
+            },
+            Shape::Polyline { points } => Shape::Polyline {
+                points: points.iter().map(point).collect(),
+            },
+        }
+    }
+
+    /// Points along the shape, with the segments between them deviating at most `tolerance`
+    /// from the true curve
+    pub fn to_polyline(&self, tolerance: Num) -> Vec<Vec2> {
+        let tolerance = tolerance.max(0.0);
+        match self {
+            Shape::Circle { center, radius } => {
+                arc_points(*center, Vec2::splat(*radius), 0.0, TAU, tolerance)
+            }
+            Shape::Arc {
+                center,
+                radius,
+                start,
+                sweep,
+            } => arc_points(*center, Vec2::splat(*radius), *start, *sweep, tolerance),
+            Shape::EllipticalArc {
+                center,
+                radii,
+                start,
+                sweep,
+            } => arc_points(*center, *radii, *start, *sweep, tolerance),
+            Shape::Quadratic {
+                start,
+                control,
+                end,
+            } => {
+                // The chord error of a segment spanning h of the curve parameter is at most
+                // |B''| h^2 / 8, with B'' = 2 (start - 2 control + end)
+                let bend = (*start - 2.0 * *control + *end).length();
+                let steps = ((bend / (4.0 * tolerance)).sqrt().ceil() as usize).clamp(1, MAX_STEPS);
+                (0..=steps)
+                    .map(|step| {
+                        let t = step as Num / steps as Num;
+                        start.lerp(*control, t).lerp(control.lerp(*end, t), t)
+                    })
+                    .collect()
+            }
+            Shape::Polyline { points } => points.clone(),
+        }
+    }
+}
+
+/// The ends of an elliptical arc and the points where it reaches furthest along an axis,
+/// for `Shape::bounds`
+fn arc_extremes(center: Vec2, radii: Vec2, start: Num, sweep: Num) -> Vec<Vec2> {
+    let at = |t: Num| center + radii * Vec2::new(t.cos(), t.sin());
+    let (from, to) = (start.min(start + sweep), start.max(start + sweep));
+    let mut points = vec![at(from), at(to)];
+    // Multiples of a quarter turn between the ends
+    let first = (from / FRAC_PI_2).ceil() as i64;
+    let last = (to / FRAC_PI_2).floor() as i64;
+    points.extend((first..=last).take(4).map(|k| at(k as Num * FRAC_PI_2)));
+    points
+}
+
+/// Most segments a curve is flattened into, however small the tolerance
+pub(super) const MAX_STEPS: usize = 4096;
+
+/// Points along an elliptical arc for `Shape::to_polyline`
+pub(super) fn arc_points(
+    center: Vec2,
+    radii: Vec2,
+    start: Num,
+    sweep: Num,
+    tolerance: Num,
+) -> Vec<Vec2> {
+    // A chord spanning an angle a of a circle with radius r deviates r (1 - cos(a / 2)) from it.
+    // Using the larger radius is conservative for ellipses
+    let radius = radii.max_element();
+    let max_angle = if tolerance < radius {
+        2.0 * (1.0 - tolerance / radius).acos()
+    } else {
+        PI
+    }
+    // A tolerance of 0 or less would take infinitely many segments
+    .max(TAU / MAX_STEPS as Num);
+    let steps = ((sweep.abs() / max_angle).ceil() as usize).clamp(1, MAX_STEPS);
+    (0..=steps)
+        .map(|step| {
+            let t = start + sweep * step as Num / steps as Num;
+            center + radii * Vec2::new(t.cos(), t.sin())
+        })
+        .collect()
+}
+
+/// A shape plus what is known about where it came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Primitive {
+    pub shape: Shape,
+    /// Depth of the source point relative to the reference plane, in mm
+    pub depth: Num,
+    pub image: ImageKind,
+    /// Name of the layer the primitive belongs to, used to style and group it
+    pub layer: String,
+    /// Position in the scribing sequence. Exporters emit primitives sorted by it
+    pub order: usize,
+    /// How hard to scribe the primitive, from 0 to 1. Machine exporters can turn it into
+    /// plunge depth or tool power to balance the brightness of the image
+    #[serde(default = "full_intensity")]
+    pub intensity: Num,
+    /// Named group the primitive is output in, such as an Inkscape or DXF layer, to scribe it
+    /// in its own pass. Primitives without one are output ungrouped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+fn full_intensity() -> Num {
+    1.0
+}
+
+/// Everything a strategy decided to scribe, independent of the output format.
+///
+/// Primitive coordinates are in viewbox units. The viewbox is fit into a canvas of
+/// `canvas_size` millimeters, keeping its aspect ratio and centered, which gives them
+/// their physical size. Plans made by a `Scriber` have a viewbox with the aspect ratio of
+/// the canvas, so it covers the canvas exactly.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScribePlan {
+    pub view_box: Rect,
+    /// Width and height of the canvas in millimeters
+    pub canvas_size: (Num, Num),
+    pub primitives: Vec<Primitive>,
+    /// Marks scribed for lining the plate up, with their positions in millimeters
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fiducials: Vec<Fiducial>,
+    /// Where the plan came from, written into every output made of it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+}
+
+impl ScribePlan {
+    pub fn new(view_box: Rect, canvas_size: (Num, Num)) -> Self {
+        Self {
+            view_box,
+            canvas_size,
+            primitives: Vec::new(),
+            fiducials: Vec::new(),
+            provenance: None,
+        }
+    }
+
+    /// Add a primitive at the end of the scribing sequence
+    pub fn push(&mut self, shape: Shape, depth: Num, image: ImageKind, layer: &str) {
+        let order = self.primitives.len();
+        self.primitives.push(Primitive {
+            shape,
+            depth,
+            image,
+            layer: layer.to_string(),
+            order,
+            intensity: full_intensity(),
+            group: None,
+        });
+    }
+
+    /// The primitives in scribing order
+    pub fn in_order(&self) -> Vec<&Primitive> {
+        let mut primitives: Vec<&Primitive> = self.primitives.iter().collect();
+        primitives.sort_by_key(|primitive| primitive.order);
+        primitives
+    }
+
+    /// The primitives in scribing order, split by group. Groups come in the order of their
+    /// first primitive
+    pub fn groups(&self) -> Vec<(Option<&str>, Vec<&Primitive>)> {
+        let mut groups: Vec<(Option<&str>, Vec<&Primitive>)> = Vec::new();
+        for primitive in self.in_order() {
+            let name = primitive.group.as_deref();
+            match groups.iter_mut().find(|(group, _)| *group == name) {
+                Some((_, primitives)) => primitives.push(primitive),
+                None => groups.push((name, vec![primitive])),
+            }
+        }
+        groups
+    }
+
+    /// Replace every curve with a polyline deviating at most `tolerance` mm from it, for
+    /// machines and software that only handle straight lines
+    pub fn flatten(&mut self, tolerance: Num) {
+        let tolerance = tolerance / self.mm_per_unit();
+        for primitive in &mut self.primitives {
+            if !matches!(primitive.shape, Shape::Polyline { .. }) {
+                let points = primitive.shape.to_polyline(tolerance);
+                primitive.shape = Shape::Polyline { points };
+            }
+        }
+    }
+
+    /// Reorder the primitives by depth, keeping the order of those at the same depth
+    pub fn sort_by_depth(&mut self) {
+        let mut primitives: Vec<&mut Primitive> = self.primitives.iter_mut().collect();
+        primitives.sort_by(|a, b| a.depth.total_cmp(&b.depth).then(a.order.cmp(&b.order)));
+        for (order, primitive) in primitives.into_iter().enumerate() {
+            primitive.order = order;
+        }
+    }
+
+    /// How many millimeters one viewbox unit is on the canvas
+    pub fn mm_per_unit(&self) -> Num {
+        (self.canvas_size.0 / self.view_box.width).min(self.canvas_size.1 / self.view_box.height)
+    }
+
+    /// The smallest rectangle containing every primitive, `None` for an empty plan
+    pub fn bounds(&self) -> Option<Rect> {
+        self.primitives
+            .iter()
+            .map(|primitive| primitive.shape.bounds())
+            .reduce(|a, b| a.union(&b))
+    }
+
+    /// Position of a point in millimeters from the top left corner of the canvas
+    pub fn to_mm(&self, point: Vec2) -> Vec2 {
+        let scale = self.mm_per_unit();
+        let canvas = Vec2::new(self.canvas_size.0, self.canvas_size.1);
+        let used = Vec2::new(self.view_box.width, self.view_box.height) * scale;
+        let origin = Vec2::new(self.view_box.x, self.view_box.y);
+        (point - origin) * scale + (canvas - used) / 2.0
+    }
+
+    /// Position in viewbox units of a point in millimeters from the top left corner of the
+    /// canvas, the inverse of `to_mm`
+    pub fn from_mm(&self, point: Vec2) -> Vec2 {
+        let offset = self.to_mm(Vec2::ZERO);
+        (point - offset) / self.mm_per_unit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_mm() {
+        // A 2x1 viewbox in a 100x100mm canvas is 50mm per unit, centered vertically
+        let plan = ScribePlan::new(Rect::new(-1.0, 0.0, 2.0, 1.0), (100.0, 100.0));
+        assert_eq!(plan.mm_per_unit(), 50.0);
+        assert_eq!(plan.to_mm(Vec2::new(-1.0, 0.0)), Vec2::new(0.0, 25.0));
+        assert_eq!(plan.to_mm(Vec2::new(1.0, 1.0)), Vec2::new(100.0, 75.0));
+    }
+
+    #[test]
+    fn test_to_polyline() {
+        let circle = Shape::Circle {
+            center: Vec2::ZERO,
+            radius: 1.0,
+        };
+        let points = circle.to_polyline(0.01);
+        assert!(points.len() > 4);
+        assert_eq!(points.first(), Some(&Vec2::new(1.0, 0.0)));
+        for pair in points.windows(2) {
+            let middle = (pair[0] + pair[1]) / 2.0;
+            assert!(1.0 - middle.length() <= 0.01);
+        }
+
+        // A tolerance of 0 gives as many segments as are allowed, not infinitely many
+        let quadratic = Shape::Quadratic {
+            start: Vec2::ZERO,
+            control: Vec2::new(1.0, 2.0),
+            end: Vec2::new(2.0, 0.0),
+        };
+        for shape in [circle, quadratic] {
+            let points = shape.to_polyline(0.0);
+            assert_eq!(points.len(), MAX_STEPS + 1);
+            assert_eq!(shape.to_polyline(-1.0).len(), points.len());
+        }
+    }
+
+    #[test]
+    fn test_bounds() {
+        // A quarter turn from the top of a circle (-y) to its right side (+x)
+        let arc = Shape::Arc {
+            center: Vec2::ZERO,
+            radius: 2.0,
+            start: -FRAC_PI_2,
+            sweep: FRAC_PI_2,
+        };
+        let bounds = arc.bounds();
+        assert!((bounds.min() - Vec2::new(0.0, -2.0)).length() < 1e-5);
+        assert!((bounds.max() - Vec2::new(2.0, 0.0)).length() < 1e-5);
+
+        // A parabola through (0, 0) and (2, 0) reaching down to y = 1
+        let quadratic = Shape::Quadratic {
+            start: Vec2::ZERO,
+            control: Vec2::new(1.0, 2.0),
+            end: Vec2::new(2.0, 0.0),
+        };
+        assert_eq!(quadratic.bounds(), Rect::new(0.0, 0.0, 2.0, 1.0));
+    }
+
+    #[test]
+    fn test_flatten() {
+        // 1 unit is 10mm, a circle of radius 10mm flattened to within 0.1mm
+        let mut plan = ScribePlan::new(Rect::new(0.0, 0.0, 10.0, 10.0), (100.0, 100.0));
+        let center = Vec2::new(5.0, 5.0);
+        plan.push(
+            Shape::Circle {
+                center,
+                radius: 1.0,
+            },
+            1.0,
+            ImageKind::Virtual,
+            "circles",
+        );
+        plan.flatten(0.1);
+        let Shape::Polyline { points } = &plan.primitives[0].shape else {
+            panic!("expected a polyline");
+        };
+        for pair in points.windows(2) {
+            let middle = (pair[0] + pair[1]) / 2.0;
+            assert!(1.0 - middle.distance(center) <= 0.01 + 1e-5);
+        }
+        assert!(points[0].distance(points[points.len() - 1]) < 1e-5);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut plan = ScribePlan::new(Rect::new(0.0, 0.0, 1.0, 1.0), (10.0, 10.0));
+        plan.push(
+            Shape::Arc {
+                center: Vec2::new(0.5, 0.5),
+                radius: 0.25,
+                start: 0.0,
+                sweep: 1.0,
+            },
+            -0.5,
+            ImageKind::Real,
+            "real-circles",
+        );
+        let json = serde_json::to_string(&plan).unwrap();
+        assert_eq!(serde_json::from_str::<ScribePlan>(&json).unwrap(), plan);
+    }
+}
@@ -0,0 +1,189 @@
+use glam::Vec3;
+use std::fmt;
+
+use super::Num;
+
+/// How the depth levels are chosen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LevelMethod {
+    /// The middles of equally deep bands between the nearest and the farthest point
+    Uniform,
+    /// The means of clusters of points found by k-means, which follow the depth distribution
+    KMeans,
+}
+
+/// One depth level, with the error of snapping its points to it in model units
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepthLevel {
+    pub z: Num,
+    pub points: usize,
+    pub mean_error: Num,
+    pub max_error: Num,
+}
+
+/// The depth levels of a quantization, with errors in mm
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantizeReport {
+    pub levels: Vec<DepthLevel>,
+}
+
+impl QuantizeReport {
+    /// The report with the errors of levels in model units converted to mm
+    pub fn new(levels: &[DepthLevel], mm_per_unit: Num) -> Self {
+        let levels = levels
+            .iter()
+            .map(|level| DepthLevel {
+                mean_error: level.mean_error * mm_per_unit,
+                max_error: level.max_error * mm_per_unit,
+                ..*level
+            })
+            .collect();
+        Self { levels }
+    }
+}
+
+impl fmt::Display for QuantizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} depth levels", self.levels.len())?;
+        for (index, level) in self.levels.iter().enumerate() {
+            write!(
+                f,
+                "\nlevel {}: z {:.3}, {} points, depth error {:.2} mm mean, {:.2} mm max",
+                index + 1,
+                level.z,
+                level.points,
+                level.mean_error,
+                level.max_error
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Snaps the depth of points to a few levels, so that every strategy scribes them with a few
+/// preset radii.
+///
+/// Quantize the points before scribing them, then sort the plan by depth to scribe all
+/// circles of a level before changing the setting.
+pub struct DepthQuantizer {
+    levels: usize,
+    method: LevelMethod,
+}
+
+impl DepthQuantizer {
+    pub fn new(levels: usize, method: LevelMethod) -> Self {
+        Self {
+            levels: levels.max(1),
+            method,
+        }
+    }
+
+    /// Snap the z of every point to its nearest level. Returns the levels that have points,
+    /// sorted by z
+    pub fn quantize(&self, points: &mut [Vec3]) -> Vec<DepthLevel> {
+        let depths: Vec<Num> = points.iter().map(|point| point.z).collect();
+        let levels = match self.method {
+            LevelMethod::Uniform => uniform_levels(&depths, self.levels),
+            LevelMethod::KMeans => kmeans_levels(&depths, self.levels),
+        };
+
+        let mut stats = vec![(0, 0.0, 0.0); levels.len()];
+        for point in points.iter_mut() {
+            let index = nearest(&levels, point.z);
+            let error = (point.z - levels[index]).abs();
+            let (count, sum, max) = &mut stats[index];
+            *count += 1;
+            *sum += error;
+            *max = error.max(*max);
+            point.z = levels[index];
+        }
+        levels
+            .into_iter()
+            .zip(stats)
+            .filter(|(_, (count, _, _))| *count > 0)
+            .map(|(z, (count, sum, max))| DepthLevel {
+                z,
+                points: count,
+                mean_error: sum / count as Num,
+                max_error: max,
+            })
+            .collect()
+    }
+}
+
+/// The middles of `count` equally deep bands over the range of depths
+fn uniform_levels(depths: &[Num], count: usize) -> Vec<Num> {
+    let min = depths.iter().copied().fold(Num::INFINITY, Num::min);
+    let max = depths.iter().copied().fold(Num::NEG_INFINITY, Num::max);
+    if max <= min {
+        return depths.first().copied().into_iter().collect();
+    }
+    let band = (max - min) / count as Num;
+    (0..count)
+        .map(|index| min + band * (index as Num + 0.5))
+        .collect()
+}
+
+/// Lloyd's algorithm in one dimension, starting from the uniform levels. Clusters that end up
+/// empty are dropped
+fn kmeans_levels(depths: &[Num], count: usize) -> Vec<Num> {
+    let mut levels = uniform_levels(depths, count);
+    for _ in 0..100 {
+        let mut sums = vec![(0.0, 0); levels.len()];
+        for &depth in depths {
+            let (sum, count) = &mut sums[nearest(&levels, depth)];
+            *sum += depth;
+            *count += 1;
+        }
+        let updated: Vec<Num> = sums
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .map(|(sum, count)| sum / count as Num)
+            .collect();
+        if updated == levels {
+            break;
+        }
+        levels = updated;
+    }
+    levels
+}
+
+/// Index of the level nearest to `depth`
+fn nearest(levels: &[Num], depth: Num) -> usize {
+    (0..levels.len())
+        .min_by(|&a, &b| {
+            (levels[a] - depth)
+                .abs()
+                .total_cmp(&(levels[b] - depth).abs())
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kmeans_levels() {
+        let mut points: Vec<Vec3> = [0.0, 0.1, 0.2, 5.0, 5.2, 10.0]
+            .into_iter()
+            .map(|z| Vec3::new(0.0, 0.0, z))
+            .collect();
+        let levels = DepthQuantizer::new(3, LevelMethod::KMeans).quantize(&mut points);
+        let zs: Vec<Num> = levels.iter().map(|level| level.z).collect();
+        assert!((zs[0] - 0.1).abs() < 1e-5 && (zs[1] - 5.1).abs() < 1e-5 && zs[2] == 10.0);
+        assert_eq!(levels[0].points, 3);
+        assert!((levels[1].max_error - 0.1).abs() < 1e-5);
+        assert_eq!(points[4].z, zs[1]);
+
+        // Uniform bands 0-3.3, 3.3-6.7 and 6.7-10 with their middles as levels
+        let mut points: Vec<Vec3> = [0.0, 4.0, 10.0]
+            .into_iter()
+            .map(|z| Vec3::new(0.0, 0.0, z))
+            .collect();
+        let levels = DepthQuantizer::new(3, LevelMethod::Uniform).quantize(&mut points);
+        assert_eq!(levels.len(), 3);
+        assert!((points[1].z - 5.0).abs() < 1e-5);
+        assert!((levels[2].max_error - 5.0 / 3.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,230 @@
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+use super::{ClipReport, ImageKind, Num, Outline, PlateClip, Rect, ScribePlan, Shape};
+
+/// Layer the registration marks of tiles are drawn into
+pub const REGISTRATION_LAYER: &str = "registration";
+
+/// How a plan is split across a grid of plates, for pieces larger than a single plate.
+///
+/// Plates are laid out from the top left corner of the canvas, each `overlap` mm over its
+/// neighbours, in as many rows and columns as it takes to cover the canvas.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Tiling {
+    /// Width and height of a plate in mm
+    pub plate_size: (Num, Num),
+    /// How far neighbouring plates overlap in mm
+    pub overlap: Num,
+    /// Scribe crosses in the middle of the seams between neighbouring plates, at the same
+    /// place on both, to line them up
+    pub registration_marks: bool,
+    /// Length of the arms of the registration crosses in mm
+    pub mark_size: Num,
+}
+
+impl Default for Tiling {
+    fn default() -> Self {
+        Self {
+            plate_size: (300.0, 300.0),
+            overlap: 0.0,
+            registration_marks: true,
+            mark_size: 3.0,
+        }
+    }
+}
+
+/// One plate of a tiled plan
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tile {
+    /// Row and column in the grid, from the top left
+    pub row: usize,
+    pub column: usize,
+    /// Top left corner of the plate in mm from the top left corner of the full canvas
+    pub origin: Vec2,
+    /// What is scribed on the plate, with a canvas the size of the plate
+    pub plan: ScribePlan,
+    /// How much of the full plan was clipped away at the borders of the plate
+    pub clip: ClipReport,
+}
+
+impl Tile {
+    /// Name of the tile, like `r1c2` for the first row and second column
+    pub fn name(&self) -> String {
+        format!("r{}c{}", self.row + 1, self.column + 1)
+    }
+}
+
+impl Tiling {
+    /// Distance in mm between the corners of neighbouring plates
+    fn step(&self) -> Vec2 {
+        (Vec2::from(self.plate_size) - self.overlap).max(Vec2::splat(Num::EPSILON))
+    }
+
+    /// Number of columns and rows of plates covering a canvas
+    pub fn grid(&self, canvas_size: (Num, Num)) -> (usize, usize) {
+        let cells = ((Vec2::from(canvas_size) - self.overlap) / self.step()).ceil();
+        (cells.x.max(1.0) as usize, cells.y.max(1.0) as usize)
+    }
+
+    /// Check that the overlap is at least 0 and smaller than the plates, so that each plate
+    /// covers some of the canvas the ones before it don't
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        let (width, height) = self.plate_size;
+        if !(0.0..width.min(height)).contains(&self.overlap) {
+            return Err(format!(
+                "overlap of {} mm must be at least 0 and smaller than the {width} x {height} mm plates",
+                self.overlap
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Split the plan into a tile per plate, row by row. Everything outside a plate is
+    /// clipped away, and registration marks are added to the scribing order of every plate.
+    /// Every tile keeps the provenance of the plan. Fails if the tiling doesn't `validate`
+    pub fn tiles(&self, plan: &ScribePlan) -> Result<Vec<Tile>, Box<dyn Error>> {
+        self.validate()?;
+        let (columns, rows) = self.grid(plan.canvas_size);
+        let step = self.step();
+        let plate = Vec2::from(self.plate_size);
+        let scale = plan.mm_per_unit();
+        let marks = self.marks(columns, rows);
+
+        let mut tiles = Vec::with_capacity(columns * rows);
+        for row in 0..rows {
+            for column in 0..columns {
+                let origin = Vec2::new(column as Num, row as Num) * step;
+                let corner = plan.from_mm(origin);
+                let view_box = Rect::new(corner.x, corner.y, plate.x / scale, plate.y / scale);
+                let mut tile_plan = ScribePlan::new(view_box, self.plate_size);
+                tile_plan.provenance = plan.provenance.clone();
+                for primitive in plan.in_order() {
+                    if intersects(&primitive.shape.bounds(), &view_box) {
+                        let mut primitive = primitive.clone();
+                        primitive.order = tile_plan.primitives.len();
+                        tile_plan.primitives.push(primitive);
+                    }
+                }
+                for &mark in &marks {
+                    let mark = plan.from_mm(mark);
+                    if intersects(&Rect::new(mark.x, mark.y, 0.0, 0.0), &view_box) {
+                        self.cross(&mut tile_plan, mark);
+                    }
+                }
+
+                let clip = PlateClip::new(Some(Outline::canvas(&tile_plan)), Vec::new());
+                let clip = clip.clip(&mut tile_plan);
+                tiles.push(Tile {
+                    row,
+                    column,
+                    origin,
+                    plan: tile_plan,
+                    clip,
+                });
+            }
+        }
+        Ok(tiles)
+    }
+
+    /// Centers in mm of the registration marks: the middles of the seams between the plates
+    fn marks(&self, columns: usize, rows: usize) -> Vec<Vec2> {
+        if !self.registration_marks {
+            return Vec::new();
+        }
+        let step = self.step();
+        let plate = Vec2::from(self.plate_size);
+        let mut marks = Vec::new();
+        for row in 0..rows {
+            for column in 0..columns {
+                let corner = Vec2::new(column as Num, row as Num) * step;
+                if column + 1 < columns {
+                    let seam = corner.x + step.x + self.overlap / 2.0;
+                    marks.push(Vec2::new(seam, corner.y + plate.y / 2.0));
+                }
+                if row + 1 < rows {
+                    let seam = corner.y + step.y + self.overlap / 2.0;
+                    marks.push(Vec2::new(corner.x + plate.x / 2.0, seam));
+                }
+            }
+        }
+        marks
+    }
+
+    /// Add a registration cross centered on a point in viewbox units
+    fn cross(&self, plan: &mut ScribePlan, center: Vec2) {
+        let arm = self.mark_size / plan.mm_per_unit();
+        for direction in [Vec2::X, Vec2::Y] {
+            let line = Shape::Polyline {
+                points: vec![center - arm * direction, center + arm * direction],
+            };
+            plan.push(line, 0.0, ImageKind::Virtual, REGISTRATION_LAYER);
+        }
+    }
+}
+
+/// Whether two rectangles overlap or touch
+fn intersects(a: &Rect, b: &Rect) -> bool {
+    a.min().cmple(b.max()).all() && b.min().cmple(a.max()).all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tiles() {
+        // 1 unit is 10mm on a 100x50mm canvas, split over 60x60mm plates overlapping 20mm
+        let mut plan = ScribePlan::new(Rect::new(0.0, 0.0, 10.0, 5.0), (100.0, 50.0));
+        plan.push(
+            Shape::Circle {
+                center: Vec2::new(5.0, 2.5),
+                radius: 0.5,
+            },
+            1.0,
+            ImageKind::Virtual,
+            "circles",
+        );
+        let tiling = Tiling {
+            plate_size: (60.0, 60.0),
+            overlap: 20.0,
+            ..Default::default()
+        };
+        assert_eq!(tiling.grid(plan.canvas_size), (2, 1));
+
+        let tiles = tiling.tiles(&plan).unwrap();
+        assert_eq!(tiles[1].name(), "r1c2");
+        assert_eq!(tiles[1].origin, Vec2::new(40.0, 0.0));
+        // The circle from 45 to 55mm is whole on both plates, at 45mm and 5mm from their left
+        for (tile, left) in tiles.iter().zip([45.0, 5.0]) {
+            let circle = &tile.plan.primitives[0];
+            assert!(matches!(circle.shape, Shape::Circle { .. }));
+            let bounds = circle.shape.bounds();
+            assert!((tile.plan.to_mm(bounds.min()).x - left).abs() < 1e-4);
+            // One cross in the middle of the overlap, at 50mm on the full canvas
+            let marks: Vec<_> = tile.plan.primitives[1..].iter().collect();
+            assert_eq!(marks.len(), 2);
+            let Shape::Polyline { points } = &marks[0].shape else {
+                panic!("expected a polyline");
+            };
+            let center = tile.plan.to_mm((points[0] + points[1]) / 2.0) + tile.origin;
+            assert!((center - Vec2::new(50.0, 30.0)).length() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_overlap_must_be_smaller_than_plates() {
+        let plan = ScribePlan::new(Rect::new(0.0, 0.0, 10.0, 5.0), (100.0, 50.0));
+        for overlap in [-1.0, 60.0, 80.0] {
+            let tiling = Tiling {
+                plate_size: (60.0, 80.0),
+                overlap,
+                ..Default::default()
+            };
+            assert!(tiling.tiles(&plan).is_err());
+        }
+    }
+}
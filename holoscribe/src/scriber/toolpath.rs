@@ -0,0 +1,362 @@
+use glam::Vec2;
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+use std::fmt;
+
+use super::{Num, Primitive, ScribePlan, Shape};
+
+/// Distance in mm the tool travels between scribes, before and after optimizing
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TravelReport {
+    pub before: Num,
+    pub after: Num,
+}
+
+impl TravelReport {
+    pub fn saved(&self) -> Num {
+        self.before - self.after
+    }
+}
+
+impl fmt::Display for TravelReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percentage = if self.before > 0.0 {
+            100.0 * self.saved() / self.before
+        } else {
+            0.0
+        };
+        write!(
+            f,
+            "travel {:.1} mm, was {:.1} mm ({:.1}% shorter)",
+            self.after, self.before, percentage
+        )
+    }
+}
+
+/// Reorders a plan to shorten the travel between scribes, starting from the top left corner
+/// of the canvas.
+///
+/// The route is built by always going to the nearest unscribed shape, then improved with
+/// 2-opt moves. Open shapes can be scribed in either direction, and closed ones are entered
+/// at the point closest to where the tool is. Both steps compare every pair of shapes, so
+/// they take time quadratic in the size of the plan.
+///
+/// Shapes are only reordered among those of the same group and layer, which are scribed in
+/// passes of their own, one after the other in the order they first come in.
+pub struct ToolpathOptimizer {
+    two_opt_passes: usize,
+    keep_depth_order: bool,
+}
+
+impl Default for ToolpathOptimizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ToolpathOptimizer {
+    pub fn new() -> Self {
+        Self {
+            two_opt_passes: 10,
+            keep_depth_order: false,
+        }
+    }
+
+    /// Also only reorder shapes at the same depth, keeping the depths in the order they
+    /// come in, for plans scribed depth level by depth level
+    pub fn keep_depth_order(mut self, keep: bool) -> Self {
+        self.keep_depth_order = keep;
+        self
+    }
+
+    /// Set the maximum number of times the route is searched for 2-opt improvements
+    pub fn two_opt_passes(mut self, passes: usize) -> Self {
+        self.two_opt_passes = passes;
+        self
+    }
+
+    pub fn optimize(&self, plan: &mut ScribePlan) -> TravelReport {
+        let scale = plan.mm_per_unit();
+        let home = plan.from_mm(Vec2::ZERO);
+        let mut original = std::mem::take(&mut plan.primitives);
+        original.sort_by_key(|primitive| primitive.order);
+        let before = travel(home, &original) * scale;
+
+        let mut route = Vec::with_capacity(original.len());
+        let mut position = home;
+        for pass in self.passes(&original) {
+            let mut part = nearest_neighbor(position, pass);
+            self.two_opt(position, &mut part);
+            // Enter closed shapes where the previous scribe left off
+            for primitive in &mut part {
+                primitive.shape = enter(&primitive.shape, position);
+                position = primitive.shape.endpoints().1;
+            }
+            route.extend(part);
+        }
+
+        let after = travel(home, &route) * scale;
+        if after >= before {
+            plan.primitives = original;
+            return TravelReport {
+                before,
+                after: before,
+            };
+        }
+        for (order, primitive) in route.iter_mut().enumerate() {
+            primitive.order = order;
+        }
+        plan.primitives = route;
+        TravelReport { before, after }
+    }
+
+    /// The primitives split into the passes they are reordered within: one per group and
+    /// layer, and per depth when keeping the depth order, in the order they first come in
+    fn passes(&self, primitives: &[Primitive]) -> Vec<Vec<Primitive>> {
+        let mut passes: Vec<Vec<Primitive>> = Vec::new();
+        let mut index = HashMap::new();
+        for primitive in primitives {
+            let depth = self.keep_depth_order.then(|| primitive.depth.to_bits());
+            let key = (&primitive.group, &primitive.layer, depth);
+            let pass = *index.entry(key).or_insert_with(|| {
+                passes.push(Vec::new());
+                passes.len() - 1
+            });
+            passes[pass].push(primitive.clone());
+        }
+        passes
+    }
+
+    /// Reverse parts of the route while that makes it shorter. Reversing a part also
+    /// reverses the direction of every shape in it
+    fn two_opt(&self, home: Vec2, route: &mut [Primitive]) {
+        let mut ends: Vec<(Vec2, Vec2)> = route.iter().map(|p| p.shape.endpoints()).collect();
+        for _ in 0..self.two_opt_passes {
+            let mut improved = false;
+            for i in 0..route.len() {
+                let before = if i == 0 { home } else { ends[i - 1].1 };
+                for j in i + 1..route.len() {
+                    let after = ends.get(j + 1).map(|end| end.0);
+                    let removed = before.distance(ends[i].0)
+                        + after.map_or(0.0, |after| ends[j].1.distance(after));
+                    let added = before.distance(ends[j].1)
+                        + after.map_or(0.0, |after| ends[i].0.distance(after));
+                    if added < removed - 1e-6 {
+                        route[i..=j].reverse();
+                        ends[i..=j].reverse();
+                        for (primitive, end) in route[i..=j].iter_mut().zip(&mut ends[i..=j]) {
+                            primitive.shape = primitive.shape.reversed();
+                            *end = (end.1, end.0);
+                        }
+                        improved = true;
+                    }
+                }
+            }
+            if !improved {
+                break;
+            }
+        }
+    }
+}
+
+/// Build a route by always scribing the nearest shape next
+fn nearest_neighbor(home: Vec2, mut remaining: Vec<Primitive>) -> Vec<Primitive> {
+    let mut route = Vec::with_capacity(remaining.len());
+    let mut position = home;
+    while !remaining.is_empty() {
+        let nearest = remaining
+            .iter()
+            .enumerate()
+            .map(|(index, primitive)| (index, entry_distance(&primitive.shape, position)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(index, _)| index)
+            .expect("there are shapes left");
+        let mut primitive = remaining.swap_remove(nearest);
+        primitive.shape = enter(&primitive.shape, position);
+        position = primitive.shape.endpoints().1;
+        route.push(primitive);
+    }
+    route
+}
+
+/// Whether the shape ends where it starts, wherever that is
+fn is_closed(shape: &Shape) -> bool {
+    match shape {
+        Shape::Circle { .. } => true,
+        Shape::Arc { sweep, .. } | Shape::EllipticalArc { sweep, .. } => sweep.abs() >= TAU,
+        _ => false,
+    }
+}
+
+/// The curve parameter of the point of a closed shape closest to `position`
+fn closest_parameter(center: Vec2, radii: Vec2, position: Vec2) -> Num {
+    // Exact for circles, close enough for ellipses
+    let offset = (position - center) / radii;
+    if offset.length_squared() > 0.0 {
+        offset.y.atan2(offset.x)
+    } else {
+        0.0
+    }
+}
+
+/// How far the tool travels from `position` to start scribing the shape
+fn entry_distance(shape: &Shape, position: Vec2) -> Num {
+    match *shape {
+        Shape::Circle { center, radius } => (position.distance(center) - radius).abs(),
+        _ if is_closed(shape) => enter(shape, position).endpoints().0.distance(position),
+        _ => {
+            let (start, end) = shape.endpoints();
+            position.distance(start).min(position.distance(end))
+        }
+    }
+}
+
+/// The shape, scribed from the point closest to `position`
+fn enter(shape: &Shape, position: Vec2) -> Shape {
+    match *shape {
+        Shape::Circle { center, radius } => {
+            let start = closest_parameter(center, Vec2::splat(radius), position);
+            if start == 0.0 {
+                shape.clone()
+            } else {
+                Shape::Arc {
+                    center,
+                    radius,
+                    start,
+                    sweep: TAU,
+                }
+            }
+        }
+        Shape::Arc {
+            center,
+            radius,
+            sweep,
+            ..
+        } if is_closed(shape) => Shape::Arc {
+            center,
+            radius,
+            start: closest_parameter(center, Vec2::splat(radius), position),
+            sweep,
+        },
+        Shape::EllipticalArc {
+            center,
+            radii,
+            sweep,
+            ..
+        } if is_closed(shape) => Shape::EllipticalArc {
+            center,
+            radii,
+            start: closest_parameter(center, radii, position),
+            sweep,
+        },
+        _ => {
+            let (start, end) = shape.endpoints();
+            if position.distance(end) < position.distance(start) {
+                shape.reversed()
+            } else {
+                shape.clone()
+            }
+        }
+    }
+}
+
+/// Total distance between the end of each shape and the start of the next, starting from
+/// `home`
+fn travel(home: Vec2, route: &[Primitive]) -> Num {
+    let mut position = home;
+    let mut distance = 0.0;
+    for primitive in route {
+        let (start, end) = primitive.shape.endpoints();
+        distance += position.distance(start);
+        position = end;
+    }
+    distance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scriber::{ImageKind, Rect};
+
+    #[test]
+    fn test_optimize_route() {
+        // 1 unit is 10mm, the canvas starts at (0, 0)
+        let mut plan = ScribePlan::new(Rect::new(0.0, 0.0, 10.0, 10.0), (100.0, 100.0));
+        let circle = |x| Shape::Circle {
+            center: Vec2::new(x, 1.0),
+            radius: 0.5,
+        };
+        for x in [7.0, 1.0, 5.0, 3.0] {
+            plan.push(circle(x), 1.0, ImageKind::Virtual, "circles");
+        }
+        // An arc best scribed from its end, which is nearest to the last circle
+        let arc = Shape::Arc {
+            center: Vec2::new(8.5, 2.0),
+            radius: 1.0,
+            start: 0.0,
+            sweep: std::f32::consts::PI,
+        };
+        plan.push(arc.clone(), 1.0, ImageKind::Virtual, "circles");
+
+        let report = ToolpathOptimizer::new().optimize(&mut plan);
+        assert!(report.after < report.before);
+
+        let route = plan.in_order();
+        let centers: Vec<Num> = route[..4]
+            .iter()
+            .map(|primitive| match primitive.shape {
+                Shape::Circle { center, .. } | Shape::Arc { center, .. } => center.x,
+                _ => panic!("expected a circle"),
+            })
+            .collect();
+        assert_eq!(centers, [1.0, 3.0, 5.0, 7.0]);
+        assert_eq!(route[4].shape, arc.reversed());
+
+        // Circles are entered at the point closest to where the previous one was left
+        let previous = route[0].shape.endpoints().1;
+        let (entry, exit) = route[1].shape.endpoints();
+        let gap = previous.distance(Vec2::new(3.0, 1.0)) - 0.5;
+        assert!((previous.distance(entry) - gap).abs() < 1e-4);
+        assert!((exit - entry).length() < 1e-4);
+    }
+
+    #[test]
+    fn test_optimize_keeps_passes() {
+        // Two groups and two depths, each written left to right across the canvas
+        let mut plan = ScribePlan::new(Rect::new(0.0, 0.0, 10.0, 10.0), (100.0, 100.0));
+        for (x, depth, group) in [
+            (1.0, 1.0, "a"),
+            (9.0, 2.0, "a"),
+            (2.0, 1.0, "b"),
+            (8.0, 2.0, "a"),
+            (3.0, 1.0, "a"),
+        ] {
+            let circle = Shape::Circle {
+                center: Vec2::new(x, 1.0),
+                radius: 0.5,
+            };
+            plan.push(circle, depth, ImageKind::Virtual, "circles");
+            plan.primitives.last_mut().unwrap().group = Some(group.to_string());
+        }
+
+        let passes = |plan: &ScribePlan| -> Vec<(Num, String)> {
+            plan.in_order()
+                .iter()
+                .map(|p| (p.depth, p.group.clone().unwrap()))
+                .collect()
+        };
+        let mut optimized = plan.clone();
+        ToolpathOptimizer::new().optimize(&mut optimized);
+        // Group b is never scribed in the middle of group a
+        let groups: Vec<String> = passes(&optimized).into_iter().map(|(_, g)| g).collect();
+        assert_eq!(groups, ["a", "a", "a", "a", "b"]);
+
+        ToolpathOptimizer::new()
+            .keep_depth_order(true)
+            .optimize(&mut plan);
+        let order = passes(&plan);
+        assert_eq!(order[0..2], [(1.0, "a".into()), (1.0, "a".into())]);
+        assert_eq!(order[2..4], [(2.0, "a".into()), (2.0, "a".into())]);
+        assert_eq!(order[4], (1.0, "b".into()));
+    }
+}
@@ -5,7 +5,7 @@ use std::path::PathBuf;
 #[command(author, version, about, long_about = None)]
 #[command(propagate_version = true)]
 pub struct Args {
-    /// Input file: expects a .svg with circles
+    /// Input file: expects a .svg with circles, or a scribe plan .json
     // #[arg]
     pub input_svg: PathBuf,
 
@@ -9,6 +9,7 @@ use svg::Document;
 
 mod cli;
 use clap::Parser;
+use holoscribe::scriber::{ScribePlan, Shape};
 
 extern crate test;
 
@@ -53,6 +54,41 @@ impl Extents {
         }
     }
 }
+
+/// Geometry of an input circle.
+#[derive(PartialEq, Debug, Copy, Clone)]
+struct CircleParams {
+    cx: f32,
+    cy: f32,
+    r: f32,
+    /// The circle encodes a point in front of the plate. Marked with
+    /// `data-image="real"` by holoscribe, or by a negative radius in older files.
+    real: bool,
+}
+
+impl CircleParams {
+    fn from_circle(circle: &Circle) -> Self {
+        let circle_attrs = circle.get_attributes();
+        let cx = circle_attrs["cx"]
+            .parse::<f32>()
+            .expect("Circle should have an x-coordinate");
+        let cy = circle_attrs["cy"]
+            .parse::<f32>()
+            .expect("Circle should have a y-coordinate");
+        let r = circle_attrs["r"]
+            .parse::<f32>()
+            .expect("Circle should have a radius");
+        let marked_real = circle_attrs
+            .get("data-image")
+            .is_some_and(|image| image.to_string() == "real");
+        CircleParams {
+            cx,
+            cy,
+            r: r.abs(),
+            real: marked_real || r < 0.0,
+        }
+    }
+}
 fn main() {
     let args = cli::Args::parse();
 
@@ -98,8 +134,7 @@ fn animate_hologram_single_svg(
     ls_max: f32,
     ly: f32,
 ) -> Result<(), std::io::Error> {
-    let svg_contents = read_svg(input_file)?;
-    let (input_circles, extents) = parse_circles_with_extents(&svg_contents);
+    let (input_circles, extents) = read_circles(input_file)?;
 
     let lss = Point {
         x: DEFAULT_WIDTH_PX * ls_min,
@@ -110,11 +145,10 @@ fn animate_hologram_single_svg(
         y: -ly,
     };
 
-    let mut viewbox = SVG::new().set("viewBox", extents.clone().as_tuple());
+    let mut viewbox = SVG::new().set("viewBox", extents.as_tuple());
     let style = Style::new(include_str!("../style.css"));
     for circle in input_circles {
-        let new_circle = circle
-            .clone()
+        let new_circle = scribed_circle(&circle)
             .set("class", "inputCircle")
             .set("stroke-width", (extents.width) * CIRCLE_STROKE_WIDTH);
         viewbox = viewbox.add(new_circle);
@@ -156,8 +190,7 @@ fn animate_hologram_multi_svg(
     ls_max: f32,
     ly: f32,
 ) -> Result<(), std::io::Error> {
-    let svg_contents = read_svg(input_file)?;
-    let (input_circles, extents) = parse_circles_with_extents(&svg_contents);
+    let (input_circles, extents) = read_circles(input_file)?;
     let step_size = DEFAULT_WIDTH_PX * (ls_max - ls_min) / num_steps as f32;
     println!(
         "Image has width of {}, using step size of {}",
@@ -197,14 +230,14 @@ fn build_hologram(
     let mut viewbox = SVG::new().set("viewBox", extents);
     let style = Style::new(include_str!("../style.css"));
     for circle in circles {
-        let new_circle = circle.clone().set("class", "inputCircle").set(
+        let new_circle = scribed_circle(circle).set("class", "inputCircle").set(
             "stroke-width",
             (extents.2 - extents.0) * CIRCLE_STROKE_WIDTH,
         );
         viewbox = viewbox.add(new_circle);
         // TODO: Rearrange arcs/circles so that arcs are always on top of circles
         // Make option for circles to not be drawn.
-        let svg_arc = arc_from_light_source(&circle, HOLO_WIDTH_DEG, &light_source)
+        let svg_arc = arc_from_light_source(circle, HOLO_WIDTH_DEG, light_source)
             .set("class", "outputArc")
             .set("stroke-width", (extents.2 - extents.0) * HOLO_STROKE_WIDTH);
         viewbox = viewbox.add(svg_arc);
@@ -218,6 +251,19 @@ fn build_hologram(
     Ok(())
 }
 
+/// Read the circles and viewBox extents of an input file, which is either
+/// an SVG or a scribe plan saved by holoscribe as .json
+fn read_circles(filename: PathBuf) -> Result<(Vec<Circle>, Extents), std::io::Error> {
+    if filename.extension().is_some_and(|ext| ext == "json") {
+        let plan_contents = std::fs::read_to_string(filename)?;
+        parse_plan_with_extents(&plan_contents)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+    } else {
+        let svg_contents = read_svg(filename)?;
+        Ok(parse_circles_with_extents(&svg_contents))
+    }
+}
+
 fn read_svg(filename: PathBuf) -> Result<String, std::io::Error> {
     let mut content = String::new();
     svg::open(filename, &mut content)?;
@@ -271,86 +317,112 @@ fn animated_arc(
     duration_secs: f32,
 ) -> Path {
     assert!(duration_secs > 0f32);
-    let circle_attrs = input_circle.get_attributes();
-    let cx = circle_attrs["cx"]
-        .parse::<f32>()
-        .expect("Circle should have an x-coordinate");
-    let cy = circle_attrs["cy"]
-        .parse::<f32>()
-        .expect("Circle should have a y-coordinate");
-    let r = circle_attrs["r"]
-        .parse::<f32>()
-        .expect("Circle should have a radius");
-    let frame_start = circular_arc_hologram_path(cx, cy, r, HOLO_WIDTH_DEG, light_source_start);
-    let frame_end = circular_arc_hologram_path(cx, cy, r, HOLO_WIDTH_DEG, light_source_end);
+    let params = CircleParams::from_circle(input_circle);
+    let frame_start = circular_arc_hologram_path(&params, HOLO_WIDTH_DEG, light_source_start);
+    let frame_end = circular_arc_hologram_path(&params, HOLO_WIDTH_DEG, light_source_end);
     let animation_data: String = [
         data_to_string(&frame_start),
         data_to_string(&frame_end),
         data_to_string(&frame_start),
     ]
     .join(";");
-    let animated_arc = Path::new().add(
+    Path::new().add(
         Animate::new()
             .set("dur", duration_secs)
             .set("repeatCount", "indefinite")
             .set("attributeName", "d")
             .set("values", animation_data),
-    );
-
-    animated_arc
+    )
 }
 
 /// Given circle parameters, a light source point, and a half-cone angle,
 /// generate a circular arc Data object representing the reflected portion
-/// of the hologram.
+/// of the hologram. Real images glint on the opposite side of the circle.
 fn circular_arc_hologram_path(
-    cx: f32,
-    cy: f32,
-    r: f32, // TODO: validate that r > 0 ?
+    circle: &CircleParams,
     half_cone_angle_deg: f32,
     light_source: &Point,
 ) -> Data {
+    let CircleParams { cx, cy, r, real } = *circle;
     let dx = light_source.x - cx;
     let dy = light_source.y - cy;
     let mut incidence_angle = (-dy / dx).atan();
     if incidence_angle < 0f32 {
         incidence_angle -= std::f32::consts::PI;
     }
+    if real {
+        incidence_angle += std::f32::consts::PI;
+    }
     let half_cone_angle_rad = half_cone_angle_deg.to_radians();
     let x0 = cx + r * (incidence_angle - half_cone_angle_rad).cos();
     let y0 = cy - r * (incidence_angle - half_cone_angle_rad).sin();
     let x = cx + r * (incidence_angle + half_cone_angle_rad).cos();
     let y = cy - r * (incidence_angle + half_cone_angle_rad).sin();
-    let path_data = Data::new()
+    Data::new()
         .move_to((x0, y0))
-        .elliptical_arc_to((r, r, 0, 0, 0, x, y));
-
-    path_data
+        .elliptical_arc_to((r, r, 0, 0, 0, x, y))
 }
 /// Given a circle, a light source, and a half-cone angle, return a Path
 /// that represents a circular arc about the point on the circle that is
 /// normal to the light source.
 fn arc_from_light_source(circle: &Circle, half_cone_angle: f32, light_source: &Point) -> Path {
-    let circle_attrs = circle.get_attributes();
-    let cx = circle_attrs["cx"]
-        .parse::<f32>()
-        .expect("Circle should have an x-coordinate");
-    let cy = circle_attrs["cy"]
-        .parse::<f32>()
-        .expect("Circle should have a y-coordinate");
-    let r = circle_attrs["r"]
-        .parse::<f32>()
-        .expect("Circle should have a radius");
     Path::new().set(
         "d",
-        circular_arc_hologram_path(cx, cy, r, half_cone_angle, light_source),
+        circular_arc_hologram_path(
+            &CircleParams::from_circle(circle),
+            half_cone_angle,
+            light_source,
+        ),
     )
 }
 
+/// Copy of an input circle with a non-negative radius, as it is scribed on the plate
+fn scribed_circle(circle: &Circle) -> Circle {
+    let params = CircleParams::from_circle(circle);
+    circle.clone().set("r", params.r)
+}
+
+/// Given the contents of a scribe plan, return a vector of Circle objects
+/// for its circles and arcs, and the extents of its viewBox. Other shapes
+/// are skipped.
+fn parse_plan_with_extents(
+    plan_contents: &str,
+) -> Result<(Vec<Circle>, Extents), serde_json::Error> {
+    let plan: ScribePlan = serde_json::from_str(plan_contents)?;
+    let mut circles = vec![];
+    for primitive in plan.in_order() {
+        match primitive.shape {
+            Shape::Circle { center, radius } | Shape::Arc { center, radius, .. } => {
+                let new_circle = Circle::new()
+                    .set("cx", center.x)
+                    .set("cy", center.y)
+                    .set("r", radius)
+                    .set("data-image", primitive.image.as_str());
+                circles.push(new_circle);
+            }
+            _ => {
+                println!("Warning: Non-circular shape in input!");
+            }
+        }
+    }
+    let extents = Extents::from_vec(vec![
+        plan.view_box.x,
+        plan.view_box.y,
+        plan.view_box.width,
+        plan.view_box.height,
+    ]);
+    Ok((circles, extents))
+}
+
+/// Parse an SVG length, ignoring its unit
+fn parse_length(length: &str) -> Result<f32, std::num::ParseFloatError> {
+    length.trim_end_matches(char::is_alphabetic).parse()
+}
+
 /// Given the contents of an SVG file, return a vector of Circle objects
 /// and the extents of the viewBox of which these circles are children.
-fn parse_circles_with_extents(svg_contents: &String) -> (Vec<Circle>, Extents) {
-    let parser = svg::Parser::new(&svg_contents);
+fn parse_circles_with_extents(svg_contents: &str) -> (Vec<Circle>, Extents) {
+    let parser = svg::Parser::new(svg_contents);
     let mut circles = vec![];
     let mut extents = Extents {
         xmin: 0.,
@@ -371,11 +443,11 @@ fn parse_circles_with_extents(svg_contents: &String) -> (Vec<Circle>, Extents) {
                         .collect();
                 } else {
                     let width = match attributes.get("width") {
-                        Some(w) => w.parse::<f32>().expect("invalid width!"),
+                        Some(w) => parse_length(w).expect("invalid width!"),
                         None => DEFAULT_WIDTH_PX,
                     };
                     let height = match attributes.get("height") {
-                        Some(h) => h.parse::<f32>().expect("invalid height!"),
+                        Some(h) => parse_length(h).expect("invalid height!"),
                         None => DEFAULT_HEIGHT_PX,
                     };
                     extent_vec = vec![0., 0., width, height];
@@ -383,15 +455,17 @@ fn parse_circles_with_extents(svg_contents: &String) -> (Vec<Circle>, Extents) {
                 extents = Extents::from_vec(extent_vec);
             }
             Event::Tag("circle", _, attributes) => {
-                let new_circle = Circle::new()
+                let mut new_circle = Circle::new()
                     .set("cx", attributes["cx"].clone())
                     .set("cy", attributes["cy"].clone())
                     .set("r", attributes["r"].clone());
+                if let Some(image) = attributes.get("data-image") {
+                    new_circle = new_circle.set("data-image", image.clone());
+                }
                 circles.push(new_circle);
             }
-            Event::Tag("svg", tag::Type::End, _) => {
-                break;
-            }
+            // Layers written by holoscribe are groups with their own copy of the viewBox
+            Event::Tag("svg", tag::Type::End, _) | Event::Tag("g", _, _) => {}
             _ => {
                 println!("Warning: Unknown SVG tag in input!");
                 continue;
@@ -478,6 +552,57 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_circle_params_real_image() {
+        let circle = Circle::new().set("cx", 1).set("cy", 2).set("r", 0.5);
+        assert!(!CircleParams::from_circle(&circle).real);
+
+        let marked = circle.clone().set("data-image", "real");
+        assert!(CircleParams::from_circle(&marked).real);
+
+        // Older files encode real images as negative radii
+        let negative = circle.set("r", -0.5);
+        let params = CircleParams::from_circle(&negative);
+        assert!(params.real);
+        assert_eq!(params.r, 0.5);
+
+        // Both at once is still real
+        let both = negative.set("data-image", "real");
+        assert!(CircleParams::from_circle(&both).real);
+    }
+
+    #[test]
+    fn test_parse_plan_extents() {
+        let plan_content = r#"
+{
+  "view_box": { "x": -1.0, "y": -2.0, "width": 3.0, "height": 4.0 },
+  "canvas_size": [100.0, 100.0],
+  "primitives": [
+    {
+      "shape": { "type": "arc", "center": [0.5, 0.0], "radius": 0.25, "start": 0.0, "sweep": 1.0 },
+      "depth": -1.0, "image": "real", "layer": "real-circles", "order": 1
+    },
+    {
+      "shape": { "type": "polyline", "points": [[0.0, 0.0], [1.0, 1.0]] },
+      "depth": 0.0, "image": "virtual", "layer": "debug", "order": 0
+    }
+  ]
+}
+        "#;
+        let (circles, extents) = parse_plan_with_extents(plan_content).unwrap();
+        assert_eq!(extents.as_tuple(), (-1., -2., 3., 4.));
+        assert_eq!(circles.len(), 1);
+        assert_eq!(
+            CircleParams::from_circle(&circles[0]),
+            CircleParams {
+                cx: 0.5,
+                cy: 0.,
+                r: 0.25,
+                real: true
+            }
+        );
+    }
+
     #[test]
     fn test_parse_extents() {
         let svg_with_viewbox = String::from(